@@ -1,18 +1,21 @@
-use std::{fs, process};
 use std::path::Path;
+use std::process;
 use console::style;
+use crate::drive::auth::{AuthFlow, GoogleAuthenticator, ListenPort, Store};
 
 pub fn do_clean(cred_path: &Path) {
-    println!("Cleaning kapy...");
+    println!("Disconnecting kapy from google drive...");
 
-    // try to remove credentials
-    print!("\tRemoving credentials...");
-    match fs::remove_file(&cred_path) {
+    // revoke the grant at google and forget the credentials locally; a
+    // missing token is treated as already-disconnected, not a failure
+    print!("\tRevoking credentials...");
+    let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::File(cred_path), AuthFlow::Browser);
+    match auth.revoke() {
         Ok(_) => println!("\t{}", style("[  OK  ]").green()),
         Err(e) => {
             println!("\t{}", style("[FAILED]").red());
-            eprintln!("Failed to remove credentials: {}", e);
+            eprintln!("Failed to revoke credentials: {}", e);
             process::exit(1);
         }
     }
-}
\ No newline at end of file
+}
@@ -1,35 +1,156 @@
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::{fs, process};
 use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{anyhow, Result};
 use core::time::Duration;
-use std::rc::Rc;
+use std::sync::Arc;
 use chrono::{DateTime, Local, LocalResult, NaiveDateTime, TimeZone};
+use clap::ValueEnum;
 use console::style;
+use rayon::prelude::*;
 use regex::Regex;
+use serde::Serialize;
 use walkdir::{WalkDir, DirEntry};
 
-use crate::processor::gps::{GpsSearch, GpxStorage, NoopGpsSearch};
+use crate::processor::gps::{GeoExport, GpsSearch, GpxStorage, NoopGpsSearch};
 use crate::drive::GoogleDrive;
-use crate::drive::auth::{CredPath, GoogleAuthenticator, ListenPort};
+use crate::drive::auth::{AuthFlow, GoogleAuthenticator, ListenPort, Store};
 use crate::config::Config;
+use crate::import_state::ImportState;
 use crate::processor;
 use crate::processor::{CloneStatistics, CloneState, image};
-use crate::processor::image::Inspection;
+use crate::processor::image::{ConversionRecord, Inspection, Operation};
+use crate::processor::remote;
+use crate::processor::remote::Sink;
 use crate::progress::{PanelType, Progress, Update};
 
 const MAX_DEPTH: usize = 10;
 const DEFAULT_MAX_SEARCH_FILES_ON_GOOGLE_DRIVE: usize = 100;
 const DEFAULT_GPS_MATCH_WITHIN: Duration = Duration::from_secs(5 * 60); // match within 5 min
 
-pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bool, after: Option<String>) {
+// how `do_clone` reports progress: `Human` drives the existing `indicatif`
+// panels, the other two print one JSON object per file to stdout instead, so
+// scripts/GUIs have a stable parse target without scraping progress bars
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    /// one pretty-printed JSON object per event
+    Json,
+    /// one single-line JSON object per event (true newline-delimited JSON)
+    JsonCompact,
+}
+
+// a single event of the `--message-format json`/`json-compact` stream;
+// `event` is "copy"/"skip"/"error" during a real clone, or "would-copy"/
+// "would-skip" under `--dry-run`, since nothing is actually written then
+#[derive(Serialize)]
+struct FileEvent {
+    event: &'static str,
+    from: String,
+    to: Option<String>,
+    reason: Option<String>,
+    bytes: Option<u64>,
+    geotag: Option<GeotagEvent>,
+}
+
+#[derive(Serialize)]
+struct GeotagEvent {
+    lat: f64,
+    lon: f64,
+    alt: f64,
+}
+
+#[derive(Serialize)]
+struct SummaryEvent {
+    event: &'static str,
+    total: usize,
+    copied: usize,
+    skipped: usize,
+    errors: usize,
+    dry_run: bool,
+}
+
+fn print_event(message_format: MessageFormat, event: &impl Serialize) {
+    let json = match message_format {
+        MessageFormat::Human => return,
+        MessageFormat::Json => serde_json::to_string_pretty(event),
+        MessageFormat::JsonCompact => serde_json::to_string(event),
+    };
+
+    match json {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to serialize event: {}", e),
+    }
+}
+
+fn is_skipped(record: &ConversionRecord) -> bool {
+    record.operations.contains(&Operation::GeoFiltered) || record.operations.contains(&Operation::SkippedExisting)
+}
+
+// turns a completed `ConversionRecord` (plus whatever gps waypoint ended up
+// matched) into the JSON event it should be reported as; mirrors the same
+// `Operation` distinctions `CloneStatistics::print_with_error` glosses over
+// in its human summary
+fn file_event(record: &ConversionRecord, matched_waypoint: Option<&gpx::Waypoint>) -> FileEvent {
+    let from = record.source.to_string_lossy().into_owned();
+    let bytes = fs::metadata(&record.source).map(|m| m.len()).ok();
+
+    if record.operations.contains(&Operation::GeoFiltered) {
+        return FileEvent {
+            event: if record.dry_run { "would-skip" } else { "skip" },
+            from,
+            to: None,
+            reason: Some("geo_filtered".to_string()),
+            bytes,
+            geotag: None,
+        };
+    }
+
+    if record.operations.contains(&Operation::SkippedExisting) {
+        return FileEvent {
+            event: if record.dry_run { "would-skip" } else { "skip" },
+            from,
+            to: Some(record.destination.to_string_lossy().into_owned()),
+            reason: Some("already_exists".to_string()),
+            bytes,
+            geotag: None,
+        };
+    }
+
+    let geotag = matched_waypoint.map(|w| GeotagEvent {
+        lat: w.point().y(),
+        lon: w.point().x(),
+        alt: w.elevation.unwrap_or(0.0),
+    });
+
+    FileEvent {
+        event: if record.dry_run { "would-copy" } else { "copy" },
+        from,
+        to: Some(record.destination.to_string_lossy().into_owned()),
+        reason: None,
+        bytes,
+        geotag,
+    }
+}
+
+pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bool, after: Option<String>, plan: bool, message_format: MessageFormat,
+                 gpx_file: Option<PathBuf>, location_history: Option<PathBuf>, geotag_gpx: Option<PathBuf>) {
+    // whether to print the human `indicatif`/`style`d narration at all; under
+    // `--message-format json`/`json-compact` only the event stream below goes
+    // to stdout, so downstream tooling gets a clean parse target
+    let human = message_format == MessageFormat::Human;
+
     // print info
     let import_from = conf.import_from().to_str().unwrap();
     let import_to = conf.import_to().to_str().unwrap();
-    println!("Cloning from {} to {}...", style(import_from).bold().cyan(),
-             style(import_to).bold().green());
+    if human {
+        println!("Cloning from {} to {}...", style(import_from).bold().cyan(),
+                 style(import_to).bold().green());
+    }
 
-    // check path existence
+    // check path existence: walking for import files is still local-disk
+    // only, but the destination may be a remote `sftp://user@host/path`
     if !conf.import_from().exists() {
         eprintln!("Invalid 'from' directory: not existed");
         process::exit(1)
@@ -38,80 +159,120 @@ pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bo
         process::exit(1)
     }
 
-    if !conf.import_to().exists() {
-        eprintln!("Invalid 'to' directory: not existed");
+    let source = remote::open_source(conf.import_from()).unwrap_or_else(|e| {
+        eprintln!("Failed to open 'from' directory: {}", e);
         process::exit(1)
-    } else if !conf.import_to().is_dir() {
-        eprintln!("Invalid 'to' directory: it is a file, not directory");
+    });
+
+    let sink = remote::open_sink(conf.import_to(), cred_path).unwrap_or_else(|e| {
+        eprintln!("Failed to open 'to' directory: {}", e);
+        process::exit(1)
+    });
+
+    if !sink.exists(conf.import_to()) {
+        eprintln!("Invalid 'to' directory: not existed");
         process::exit(1)
     }
 
-    // calculate when to copy started (since the last save to 'conf.to_path')
-    let to_be_import_after = match after {
+    // load the dirstate-style cache of what's already been imported into
+    // this destination, so unchanged source files turned up by the walk
+    // below can be skipped without re-inspecting/re-cloning them
+    let mut import_state = ImportState::load(conf.import_to()).unwrap_or_else(|e| {
+        eprintln!("Failed to load import state: {}", e);
+        process::exit(1);
+    });
+
+    // get to import files
+    let all_entries = import_entries(conf.import_from());
+
+    // filter import files to retrieve: an explicit `--after` date overrides
+    // the cache entirely and filters by creation time, same as before;
+    // otherwise skip whatever the import state already has recorded as
+    // imported, by (relative path, size, mtime)
+    let import_entries: Vec<DirEntry> = match &after {
         Some(after) => {
             // valid: YYYY or YYYY-MM-DD or YYYY-MM
-            match system_time_from_str(&after) {
-                Ok(t) => Some(t),
+            let t = match system_time_from_str(after) {
+                Ok(t) => t,
                 Err(_) => {
                     eprintln!("Invalid time format: YYYY-MM-DD or YYYY-MM or YYYY are valid");
                     process::exit(1);
                 }
-            }
+            };
+
+            all_entries.iter().filter(|entry| {
+                let entry_created_at = entry.metadata().unwrap().created().unwrap();
+                entry_created_at > t
+            }).cloned().collect()
         }
         None => {
-            match to_be_imported_after(conf.import_to()) {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Failed to determine date and time to be imported after: {}", e);
-                    process::exit(1);
-                }
-            }
+            all_entries.iter().filter(|entry| {
+                let metadata = entry.metadata().unwrap();
+                let rel_path = relative_path(conf.import_from(), entry.path());
+
+                !import_state.already_imported(&rel_path, metadata.len(), metadata.modified().unwrap())
+            }).cloned().collect()
         }
     };
 
-    // get to import files
-    let import_entries = import_entries(conf.import_from());
-
-    // filter import files to retrieve
-    let import_entries = match to_be_import_after {
-        Some(t) => {
-            import_entries.into_iter().filter(|entry| {
-                let entry_created_at = entry.metadata().unwrap().created().unwrap();
-                entry_created_at > t
-            }).collect()
-        }
-        None => import_entries
+    // the entries `--plan` should list as filtered out - everything the
+    // walk turned up that isn't making it into this run
+    let skipped_entries: Vec<DirEntry> = if plan {
+        let included: std::collections::HashSet<&Path> = import_entries.iter().map(|e| e.path()).collect();
+        all_entries.iter().filter(|e| !included.contains(e.path())).cloned().collect()
+    } else {
+        Vec::new()
     };
 
-    // inspection for each images
+    // make this process's rayon global pool match `conf.workers()` before the
+    // first parallel phase below spins it up implicitly
+    image::prelude(conf.workers());
+
+    // inspection for each images, across `conf.workers()` threads: decode +
+    // EXIF parse is embarrassingly parallel per file, and inspection only
+    // ever reads the local disk directly (never through `source`), so there's
+    // nothing here that needs to be serialized
     let mut inspections = Vec::new();
     {
-        println!("{} {}", style("Inspecting").green().bold(), import_from);
-        let progress = Progress::new(vec![
-            PanelType::Bar("files_bar", import_entries.len() as u64),
-            PanelType::Message("state"),
-        ]);
+        if human {
+            println!("{} {}", style("Inspecting").green().bold(), import_from);
+        }
+        let progress = Progress::new(if human {
+            vec![
+                PanelType::Bar("files_bar", import_entries.len() as u64),
+                PanelType::Message("state"),
+            ]
+        } else {
+            Vec::new()
+        });
 
-        for entry in import_entries.iter() {
-            progress.update("files_bar", Update::Incr(None));
+        let results: Vec<(&DirEntry, Result<Inspection>)> = import_entries.par_iter()
+            .map(|entry| {
+                progress.update("files_bar", Update::Incr(None));
 
-            let path = entry.path();
-            let path_str = path.to_str().unwrap();  // never failed
-            progress.update("state", Update::Incr(Some(format!("{}: inspecting...", style(path_str).bold()))));
+                let path = entry.path();
+                let path_str = path.to_str().unwrap();  // never failed
+                progress.update("state", Update::Incr(Some(format!("{}: inspecting...", style(path_str).bold()))));
+
+                (entry, image::inspect_image_from_path(path))
+            })
+            .collect();
 
-            let inspection = match image::inspect_image_from_path(path) {
-                Ok(inspection) => inspection,
+        progress.finish_all();
+
+        for (entry, result) in results {
+            match result {
+                Ok(inspection) => inspections.push(inspection),
                 Err(e) => {
-                    eprintln!("Failed to inspection image '{}': {}", path_str, e);
+                    eprintln!("Failed to inspection image '{}': {}", entry.path().to_str().unwrap(), e);
                     process::exit(1);
                 }
-            };
-
-            inspections.push(inspection);
+            }
         }
 
-        progress.finish_all();
-        progress.println(format!("{:>5} files are inspected", style(inspections.len()).cyan().bold()));
+        if human {
+            progress.println(format!("{:>5} files are inspected", style(inspections.len()).cyan().bold()));
+        }
         progress.clear();
     }
 
@@ -124,26 +285,58 @@ pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bo
         }
     };
 
-    // make gps search trait object
-    let gps_search: Rc<Box<dyn GpsSearch>> = if ignore_geotag {
-        Rc::new(Box::new(NoopGpsSearch))
+    // when --geotag-gpx is set, each photo is geotagged directly against its
+    // own DateTimeOriginal via `geotag::geotag_photo` (see below) rather than
+    // through the `GpsSearch`/`GeoCache` bucketed-search machinery, so the
+    // trait object here is left a no-op
+    let geotag_gpx: Option<Arc<Vec<u8>>> = geotag_gpx.map(|path| {
+        fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to read GPX track '{}': {}", path.display(), e);
+            process::exit(1);
+        })
+    }).map(Arc::new);
+
+    // make gps search trait object: `Arc` rather than `Rc` since the clone
+    // loop below shares this across the rayon pool
+    let gps_search: Arc<Box<dyn GpsSearch>> = if ignore_geotag || geotag_gpx.is_some() {
+        Arc::new(Box::new(NoopGpsSearch))
+    } else if let Some(gpx_file) = gpx_file {
+        match GpxStorage::from_file(&gpx_file, DEFAULT_GPS_MATCH_WITHIN) {
+            Ok(search) => Arc::new(Box::new(search)),
+            Err(e) => {
+                eprintln!("Failed to load GPX track from '{}': {}", gpx_file.display(), e);
+                process::exit(1);
+            }
+        }
+    } else if let Some(location_history) = location_history {
+        match GpxStorage::from_location_history(&location_history, DEFAULT_GPS_MATCH_WITHIN) {
+            Ok(search) => Arc::new(Box::new(search)),
+            Err(e) => {
+                eprintln!("Failed to load location history from '{}': {}", location_history.display(), e);
+                process::exit(1);
+            }
+        }
     } else {
         // adjust time to more flexibility (+ 1 hour)
         let start = oldest_created_at - Duration::from_secs(3600);
         let end = most_recent_created_at + Duration::from_secs(3600);
 
         // make a progress
-        println!("{} from google drive: {} ~ {}",
-                 style("Preparing GPX").green().bold(),
-                 style(start.to_string()).cyan(), style(end.to_string()).cyan());
-        let progress = Progress::new(vec![
-            PanelType::Message("gpx_filename"),
-        ]);
+        if human {
+            println!("{} from google drive: {} ~ {}",
+                     style("Preparing GPX").green().bold(),
+                     style(start.to_string()).cyan(), style(end.to_string()).cyan());
+        }
+        let progress = Progress::new(if human {
+            vec![PanelType::Message("gpx_filename")]
+        } else {
+            Vec::new()
+        });
 
         // initialize google drive
         let mut count = 0;
 
-        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, CredPath::Path(cred_path));
+        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::File(cred_path), AuthFlow::Browser);
         let drive = GoogleDrive::new(auth);
 
         match GpxStorage::from_google_drive(&drive, start, end,
@@ -155,10 +348,12 @@ pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bo
                                             }) {
             Ok(search) => {
                 progress.finish_all();
-                progress.println(format!("{:>5} gpx files are retrieved", style(count).cyan().bold()));
+                if human {
+                    progress.println(format!("{:>5} gpx files are retrieved", style(count).cyan().bold()));
+                }
                 progress.clear();
 
-                Rc::new(Box::new(search))
+                Arc::new(Box::new(search))
             }
             Err(e) => {
                 eprintln!("Failed to initialize geotag search on your google drive: {}", e);
@@ -167,57 +362,228 @@ pub fn do_clone(conf: Config, cred_path: &Path, ignore_geotag: bool, dry_run: bo
         }
     };
 
+    // shared across the real clone loop's workers below (by `&` reference,
+    // same as `progress`), so `{counter}` is monotonic and template
+    // collisions are caught across the entire run, not just within one
+    // worker thread; the plan preview gets its own `TemplateRun` so its
+    // reservations don't push the real run onto suffixed names and later
+    // counters (the parallel loop may still hand out `{counter}` values and
+    // collision suffixes in a different order than the sequential preview)
+    let template_run = processor::template::TemplateRun::new();
+
+    if plan {
+        let plan_template_run = processor::template::TemplateRun::new();
+        if !print_plan(&conf, &inspections, &skipped_entries, sink.as_ref(), Arc::clone(&gps_search), &plan_template_run) {
+            println!("Aborted");
+            return;
+        }
+    }
+
     // process clone
     let mut clone_statistics = CloneStatistics::new();
     let total_images = import_entries.len();
     let mut errors = Vec::new();
-
-    // make progress
+    let mut manifest: Vec<ConversionRecord> = Vec::new();
+    let mut geo_export = GeoExport::new();
+
+    // make progress: `clone_image` runs across `conf.workers()` threads via
+    // rayon, each reporting through the same `progress`/`gps_search` (shared
+    // behind `&`/`Arc` respectively, both `Sync`); results are reduced into
+    // `clone_statistics`/`manifest`/`geo_export`/`errors` afterwards, back on
+    // this thread, since those collections themselves aren't thread-safe
     {
-        println!("{} {}", style("Cloning").green().bold(), import_to);
-        let progress = Progress::new(vec![
-            PanelType::Bar("files_bar", import_entries.len() as u64),
-            PanelType::Message("state"),
-        ]);
-
-        for inspection in inspections.iter() {
-            progress.update("files_bar", Update::Incr(None));
-            let gps_search = Rc::clone(&gps_search);
-
-            match processor::clone_image(&conf, &inspection.path, conf.import_to(),
-                                         inspection,
-                                         gps_search, dry_run,
-                                         |state| {
-                                             match state {
-                                                 CloneState::AddGps(in_path) => {
-                                                     progress.update("state", Update::Incr(Some(format!("{}: adding gps info...", style(in_path).bold()))));
-                                                 }
-                                                 CloneState::Reading(in_path) => {
-                                                     progress.update("state", Update::Incr(Some(format!("{}: reading...", style(in_path).bold()))));
-                                                 }
-                                                 CloneState::Copying(in_path, out_path) => {
-                                                     progress.update("state", Update::Incr(Some(format!("{} {} {}: copying...", style(in_path).cyan(), style("→").bold(), style(out_path).green()))));
-                                                 }
-                                                 CloneState::Converting(in_path, out_path, cmd) => {
-                                                     progress.update("state", Update::Incr(Some(format!("{} {} {}: converting {}...", style(in_path).cyan(), style("→").bold(), style(out_path).green(), style(cmd).dim()))));
+        if human {
+            println!("{} {}", style("Cloning").green().bold(), import_to);
+        }
+        let progress = Progress::new(if human {
+            vec![
+                PanelType::Bar("files_bar", import_entries.len() as u64),
+                PanelType::Message("state"),
+            ]
+        } else {
+            Vec::new()
+        });
+
+        let results: Vec<(&Inspection, Result<(CloneStatistics, ConversionRecord, Option<gpx::Waypoint>)>)> = inspections.par_iter()
+            .map(|inspection| {
+                progress.update("files_bar", Update::Incr(None));
+                let gps_search = Arc::clone(&gps_search);
+                let geotag_gpx = geotag_gpx.clone();
+
+                let result = processor::clone_image(&conf, &inspection.path, conf.import_to(),
+                                             source.as_ref(), sink.as_ref(),
+                                             inspection,
+                                             gps_search, geotag_gpx, dry_run,
+                                             &template_run,
+                                             |state| {
+                                                 match state {
+                                                     CloneState::AddGps(in_path) => {
+                                                         progress.update("state", Update::Incr(Some(format!("{}: adding gps info...", style(in_path).bold()))));
+                                                     }
+                                                     CloneState::Reading(in_path) => {
+                                                         progress.update("state", Update::Incr(Some(format!("{}: reading...", style(in_path).bold()))));
+                                                     }
+                                                     CloneState::Copying(in_path, out_path) => {
+                                                         progress.update("state", Update::Incr(Some(format!("{} {} {}: copying...", style(in_path).cyan(), style("→").bold(), style(out_path).green()))));
+                                                     }
+                                                     CloneState::Converting(in_path, out_path, cmd) => {
+                                                         progress.update("state", Update::Incr(Some(format!("{} {} {}: converting {}...", style(in_path).cyan(), style("→").bold(), style(out_path).green(), style(cmd).dim()))));
+                                                     }
                                                  }
-                                             }
-                                         }) {
-                Ok(stat) => {
+                                             });
+
+                (inspection, result)
+            })
+            .collect();
+
+        progress.finish_all();
+        progress.clear();
+
+        for (inspection, result) in results {
+            match result {
+                Ok((stat, record, matched_waypoint)) => {
+                    print_event(message_format, &file_event(&record, matched_waypoint.as_ref()));
+
                     clone_statistics = clone_statistics + stat;
+                    manifest.push(record);
+
+                    // a file only reaches here once the clone pipeline has
+                    // fully considered it, regardless of whether it was
+                    // actually copied/converted or filtered/skipped - either
+                    // way there's no need to look at it again next run
+                    if !dry_run {
+                        if let Ok(metadata) = fs::metadata(&inspection.path) {
+                            if let Ok(mtime) = metadata.modified() {
+                                let rel_path = relative_path(conf.import_from(), &inspection.path);
+                                import_state.mark_imported(rel_path, metadata.len(), mtime);
+                            }
+                        }
+                    }
+
+                    if let Some(waypoint) = matched_waypoint {
+                        let photo_name = inspection.path.file_name().unwrap().to_str().unwrap().to_string();
+                        geo_export.push(photo_name, waypoint);
+                    }
                 }
                 Err(e) => {
+                    print_event(message_format, &FileEvent {
+                        event: "error",
+                        from: inspection.path.to_string_lossy().into_owned(),
+                        to: None,
+                        reason: Some(e.to_string()),
+                        bytes: None,
+                        geotag: None,
+                    });
+
                     errors.push((inspection, e));
                 }
             }
         }
+    }
 
-        progress.finish_all();
-        progress.clear();
+    // persist the updated import state, so the next run can skip everything
+    // that succeeded this time
+    if !dry_run {
+        if let Err(e) = import_state.save(conf.import_to()) {
+            eprintln!("Failed to save import state: {}", e);
+        }
     }
 
     // print-out clone statistics
-    clone_statistics.print_with_error(total_images, &errors);
+    if human {
+        clone_statistics.print_with_error(total_images, &errors);
+    } else {
+        let skipped = manifest.iter().filter(|r| is_skipped(r)).count();
+        print_event(message_format, &SummaryEvent {
+            event: "summary",
+            total: total_images,
+            copied: manifest.len() - skipped,
+            skipped,
+            errors: errors.len(),
+            dry_run,
+        });
+    }
+
+    // write-out structured manifest, if requested
+    if let Some(manifest_path) = conf.manifest_path() {
+        match write_manifest(manifest_path, &manifest) {
+            Ok(()) => {
+                if human {
+                    println!("Manifest written to {}", style(manifest_path.to_str().unwrap()).bold().green());
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to write manifest to '{}': {}", manifest_path.to_str().unwrap(), e);
+            }
+        }
+    }
+
+    // write-out a round-trippable record of the geotagging result, if anything got matched
+    if !geo_export.is_empty() {
+        let geo_export_path = conf.import_to().join("kapy-geotagged.gpx");
+
+        match geo_export.write_to_file(&geo_export_path) {
+            Ok(()) => {
+                if human {
+                    println!("Geotag export written to {}", style(geo_export_path.to_str().unwrap()).bold().green());
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to write geotag export to '{}': {}", geo_export_path.to_str().unwrap(), e);
+            }
+        }
+    }
+}
+
+// prints the full source -> destination mapping `--plan` requested, without
+// touching the filesystem, then asks whether to proceed; entries excluded by
+// `--after`/the import-state cache are listed separately from
+// `image::PlanAction::SkipExisting`, which is only decided once a destination
+// is resolved
+fn print_plan(conf: &Config, inspections: &[Inspection], skipped_entries: &[DirEntry],
+             sink: &dyn Sink, gps_search: Arc<Box<dyn GpsSearch>>, template_run: &processor::template::TemplateRun) -> bool {
+    println!("{}", style("Plan").green().bold());
+
+    for entry in skipped_entries {
+        println!("  {} {} {}", style("-").dim(),
+                 entry.path().to_str().unwrap(), style("skip (filtered by --after or already imported)").dim());
+    }
+
+    for inspection in inspections {
+        let gps_search = Arc::clone(&gps_search);
+
+        match processor::plan_clone_item(conf, &inspection.path, conf.import_to(), sink, inspection, gps_search, template_run) {
+            Ok((destination, action)) => {
+                println!("  {} {} {} {} ({})",
+                         style("-").dim(),
+                         inspection.path.to_str().unwrap(),
+                         style("→").bold(),
+                         destination.to_str().unwrap_or(""),
+                         style(action.to_string()).cyan());
+            }
+            Err(e) => {
+                eprintln!("Failed to plan '{}': {}", inspection.path.to_str().unwrap(), e);
+            }
+        }
+    }
+
+    println!("{}", style("---").dim());
+    print!("Continue with this clone? [y/N] ");
+    io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn write_manifest(path: &Path, manifest: &Vec<ConversionRecord>) -> Result<()> {
+    let json = serde_json::to_string_pretty(manifest)?;
+    fs::write(path, json)?;
+
+    Ok(())
 }
 
 fn import_entries(dir: &Path) -> Vec<DirEntry> {
@@ -254,22 +620,13 @@ const RE_ONLY_YEAR: &str = "^[0-9]{4}$";
 const RE_YEAR_MONTH: &str = r"(?P<year>[0-9]{4})-(?P<month>[0-9]{2})$";
 const RE_YEAR_MONTH_DAY: &str = r"(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})$";
 
-fn to_be_imported_after(out_dir: &Path) -> Result<Option<SystemTime>> {
-    // find first-level: e.g., 2023
-    let first_depth_dir = get_last_modified_dir(out_dir, Some(RE_ONLY_YEAR))?;
-    if let Some(first_depth_dir) = first_depth_dir {
-        // find second-level: e.g., 2023-02-16
-        return if let Some(second_depth_dir) = get_last_modified_dir(&first_depth_dir, Some(RE_YEAR_MONTH_DAY))? {
-            let t = system_time_from_str(second_depth_dir.file_name().unwrap().to_str().unwrap())?;
-            Ok(Some(t))
-        } else {
-            // get first day of given year
-            let first_day_of_year = system_time_from_str(first_depth_dir.file_name().unwrap().to_str().unwrap())?;
-            Ok(Some(first_day_of_year))
-        };
-    }
-
-    Ok(None)
+// the source-relative key `ImportState` caches entries under, stable across
+// moves of the destination tree (unlike an absolute path)
+fn relative_path(import_from: &Path, path: &Path) -> String {
+    path.strip_prefix(import_from)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
 }
 
 fn walk_and_filter_only_supported_images(dir: &Path) -> Vec<DirEntry> {
@@ -292,6 +649,7 @@ fn walk_and_filter_only_supported_images(dir: &Path) -> Vec<DirEntry> {
             if let Some(ext) = path.extension()?.to_str() {
                 return match ext.to_lowercase().as_str() {
                     "jpeg" | "jpg" | "heic" => Some(entry),
+                    _ if processor::raw::is_raw_file(path) => Some(entry),
                     _ => None,
                 };
             }
@@ -304,39 +662,6 @@ fn walk_and_filter_only_supported_images(dir: &Path) -> Vec<DirEntry> {
     entries
 }
 
-fn get_last_modified_dir(dir: &Path, re_pattern: Option<&str>) -> Result<Option<PathBuf>> {
-    let mut last_modified: Option<PathBuf> = None;
-
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-
-        if entry.file_type()?.is_dir() {
-            if let Some(pattern) = re_pattern {
-                if let Some(filename) = entry.file_name().to_str() {
-                    let re = Regex::new(pattern)?;
-                    if !re.is_match(filename) {
-                        continue;
-                    }
-                } else {
-                    continue;
-                }
-            }
-
-            if let Some(ref prev_entry) = last_modified {
-                let prev_modified_time = prev_entry.metadata()?.modified()?;
-                let modified_time = entry.metadata()?.modified()?;
-                if modified_time > prev_modified_time {
-                    last_modified = Some(entry.path());
-                }
-            } else {
-                last_modified = Some(entry.path());
-            }
-        }
-    }
-
-    Ok(last_modified)
-}
-
 fn system_time_from_str(s: &str) -> Result<SystemTime> {
     let re_only_year = Regex::new(RE_ONLY_YEAR)?;
     let re_year_month = Regex::new(RE_YEAR_MONTH)?;
@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
@@ -6,13 +7,94 @@ use std::rc::Rc;
 use regex::Regex;
 use serde::Deserialize;
 
+use crate::processor::gps::GeoFilter;
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     import: ImportPath,
     policies: Vec<Policy>,
 
+    // number of worker threads used to convert images in parallel; defaults
+    // to the number of logical cores when unset
+    workers: Option<usize>,
+
+    // path to write the structured conversion manifest (JSON) to after a
+    // clone; manifest is not written when unset
+    manifest: Option<PathBuf>,
+
+    // restricts which photos get copied to those whose matched GPS
+    // coordinates fall inside this area; no filtering is applied when unset
+    geo_filter: Option<GeoFilter>,
+
+    // number of threads the AVIF encoder spawns for itself, per file; since
+    // `workers` already parallelizes across files, this defaults to 1 to
+    // avoid oversubscribing the machine with nested thread pools
+    avif_threads: Option<usize>,
+
+    // `{token}`/`{token:04}` destination path template, expanded per file by
+    // `processor::template`; falls back to the hard-coded `<year>/<YYYY-MM-DD>`
+    // layout when unset
+    output_template: Option<String>,
+
+    // skip matching photos against GPS tracks altogether; defaults to false
+    ignore_geotag: Option<bool>,
+
+    // port the OAuth2 loopback redirect server listens on during `login`;
+    // falls back to `ListenPort::DefaultPort` when unset
+    listen_port: Option<i32>,
+
+    // where the google drive credential is read from/written to; falls back
+    // to `config::default_path().cred_path()` when unset
+    cred_path: Option<PathBuf>,
+
+    // named sections selectable with `--profile`, for users juggling more
+    // than one camera workflow (e.g. personal vs. client) from one config
+    // file; a profile only needs to set what differs from the top-level
+    // defaults above
+    profiles: Option<BTreeMap<String, ProfileConfig>>,
+
     #[serde(skip_deserializing)]
     commands: BTreeMap<i8, Command>,
+
+    // where each resolved value in this config ultimately came from, filled
+    // in by `resolve`; keyed by the same names used in `resolve`/`print_provenance`
+    #[serde(skip_deserializing)]
+    provenance: BTreeMap<String, Source>,
+}
+
+/// Where an effective config value came from, in increasing precedence:
+/// a built-in default, the config file, a `KAPY_*` environment variable,
+/// then a CLI flag. See `Config::resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    ConfigFile,
+    Env,
+    Cli,
+}
+
+impl Source {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::ConfigFile => "config file",
+            Source::Env => "environment",
+            Source::Cli => "CLI flag",
+        }
+    }
+}
+
+/// The CLI-flag layer fed into `Config::resolve`, gathered once the
+/// subcommand's arguments are known. A `None` field means this invocation
+/// didn't touch that key, letting the env/config-file layers underneath
+/// show through instead.
+#[derive(Default)]
+pub struct CliOverrides {
+    pub import_from: Option<PathBuf>,
+    pub import_to: Option<PathBuf>,
+    pub ignore_geotag: Option<bool>,
+    pub listen_port: Option<i32>,
+    pub cred_path: Option<PathBuf>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -21,9 +103,18 @@ pub struct ImportPath {
     to: PathBuf,
 }
 
+// a profile only overrides what it sets; anything left unset falls back to
+// the top-level default profile's value
+#[derive(Deserialize, Debug)]
+struct ProfileConfig {
+    import: Option<ImportPath>,
+    policies: Option<Vec<Policy>>,
+    cred_path: Option<PathBuf>,
+}
+
 type UnparsedCommand = Option<BTreeMap<String, String>>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 struct Policy {
     rate: Vec<i8>,
     command: UnparsedCommand,
@@ -39,34 +130,13 @@ impl Policy {
         let mut resize: Option<Resize> = None;
         let mut format: Option<Format> = None;
         let mut quality: Option<Quality> = None;
+        let mut blurhash: Option<(u8, u8)> = None;
 
-        // resize: 100% or 50m or preserve
+        // resize: 100% / 50m / 1920x1080 / 2048> / w1600 / h1200 / preserve
         if let Some(opt) = m.get("resize") {
             let opt = opt.clone().to_lowercase();
 
-            let re = Regex::new(r"(?P<val>[0-9]+)(?P<postfix>[%m]{1})$").unwrap();
-            if let Some(captures) = re.captures(&opt) {
-                let vals = captures.name("val").unwrap().as_str();
-                let val = vals.parse::<u8>().unwrap_or(100);
-
-                let postfix = captures.name("postfix").unwrap().as_str();
-
-                match postfix {
-                    "%" => {
-                        if val <= 100 {
-                            resize = Some(Resize::Percentage(val));
-                        }
-                    }
-                    "m" => {
-                        resize = Some(Resize::MPixels(val));
-                    }
-                    _ => {
-                        resize = Some(Resize::Preserve);
-                    }
-                }
-            } else {
-                return Err(format!("Invalid resize option from '{}'", opt));
-            }
+            resize = Some(parse_resize(&opt)?);
         }
 
         // format
@@ -80,6 +150,12 @@ impl Policy {
                 "jpg" | "jpeg" => {
                     format = Some(Format::JPEG);
                 }
+                "avif" => {
+                    format = Some(Format::AVIF);
+                }
+                "webp" => {
+                    format = Some(Format::WebP);
+                }
                 "preserve" => {
                     format = Some(Format::Preserve);
                 }
@@ -103,11 +179,28 @@ impl Policy {
             }
         }
 
-        if !resize.is_none() || !format.is_none() || !quality.is_none() {
+        // blurhash: component counts, e.g. `4x3`; opt-in, no placeholder is
+        // generated when unset
+        if let Some(opt) = m.get("blurhash") {
+            let opt = opt.clone().to_lowercase();
+
+            let re = Regex::new(r"^(?P<x>[0-9]+)x(?P<y>[0-9]+)$").unwrap();
+            if let Some(captures) = re.captures(&opt) {
+                let x = captures.name("x").unwrap().as_str().parse::<u8>().unwrap_or(4);
+                let y = captures.name("y").unwrap().as_str().parse::<u8>().unwrap_or(3);
+
+                blurhash = Some((x, y));
+            } else {
+                return Err(format!("Invalid blurhash option from '{}'", opt));
+            }
+        }
+
+        if !resize.is_none() || !format.is_none() || !quality.is_none() || !blurhash.is_none() {
             return Ok(Command::Convert {
                 resize: resize.unwrap_or(Resize::Preserve),
                 format: format.unwrap_or(Format::Preserve),
                 quality: quality.unwrap_or(Quality::Preserve),
+                blurhash,
             });
         }
 
@@ -122,6 +215,9 @@ pub enum Command {
         resize: Resize,
         format: Format,
         quality: Quality,
+        // BlurHash component counts (x, y), e.g. (4, 3); no placeholder is
+        // generated when unset
+        blurhash: Option<(u8, u8)>,
     },
 }
 
@@ -129,13 +225,68 @@ pub enum Command {
 pub enum Resize {
     Percentage(u8),
     MPixels(u8),
+    // fit inside a `width`x`height` box, preserving aspect ratio
+    Box(u32, u32),
+    // scale the long edge down to this many pixels, but only if it's
+    // currently larger; a no-op for images that are already smaller
+    LongEdgeIfLarger(u32),
+    // constrain the width to this many pixels, preserving aspect ratio
+    Width(u32),
+    // constrain the height to this many pixels, preserving aspect ratio
+    Height(u32),
     Preserve,
 }
 
+// parses the resize grammar accepted under a policy's `resize:` key:
+// `100%`, `50m`, `1920x1080`, `2048>`, `w1600`, `h1200`, `preserve`
+fn parse_resize(opt: &str) -> Result<Resize, String> {
+    if opt == "preserve" {
+        return Ok(Resize::Preserve);
+    }
+
+    if let Some(captures) = Regex::new(r"^(?P<val>[0-9]+)(?P<postfix>[%m])$").unwrap().captures(opt) {
+        let val = captures.name("val").unwrap().as_str().parse::<u8>().unwrap_or(100);
+
+        return Ok(match captures.name("postfix").unwrap().as_str() {
+            "%" => Resize::Percentage(val),
+            _ => Resize::MPixels(val),
+        });
+    }
+
+    if let Some(captures) = Regex::new(r"^(?P<width>[0-9]+)x(?P<height>[0-9]+)$").unwrap().captures(opt) {
+        let width = captures.name("width").unwrap().as_str().parse::<u32>().unwrap_or(0);
+        let height = captures.name("height").unwrap().as_str().parse::<u32>().unwrap_or(0);
+
+        return Ok(Resize::Box(width, height));
+    }
+
+    if let Some(captures) = Regex::new(r"^(?P<val>[0-9]+)>$").unwrap().captures(opt) {
+        let val = captures.name("val").unwrap().as_str().parse::<u32>().unwrap_or(0);
+
+        return Ok(Resize::LongEdgeIfLarger(val));
+    }
+
+    if let Some(captures) = Regex::new(r"^w(?P<val>[0-9]+)$").unwrap().captures(opt) {
+        let val = captures.name("val").unwrap().as_str().parse::<u32>().unwrap_or(0);
+
+        return Ok(Resize::Width(val));
+    }
+
+    if let Some(captures) = Regex::new(r"^h(?P<val>[0-9]+)$").unwrap().captures(opt) {
+        let val = captures.name("val").unwrap().as_str().parse::<u32>().unwrap_or(0);
+
+        return Ok(Resize::Height(val));
+    }
+
+    Err(format!("Invalid resize option from '{}'", opt))
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Format {
     JPEG,
     HEIC,
+    AVIF,
+    WebP,
     Preserve,
 }
 
@@ -144,9 +295,22 @@ impl Format {
         match self {
             Format::JPEG => "JPEG",
             Format::HEIC => "HEIC",
+            Format::AVIF => "AVIF",
+            Format::WebP => "WEBP",
             Format::Preserve => "",
         }
     }
+
+    pub fn from_str(s: &str) -> anyhow::Result<Format> {
+        match s.to_uppercase().as_str() {
+            "JPEG" => Ok(Format::JPEG),
+            "HEIC" => Ok(Format::HEIC),
+            "AVIF" => Ok(Format::AVIF),
+            "WEBP" => Ok(Format::WebP),
+            "" => Ok(Format::Preserve),
+            _ => Err(anyhow::anyhow!("Invalid format string '{}'", s)),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -212,9 +376,254 @@ impl Config {
         &self.import.to
     }
 
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = Some(workers);
+    }
+
     pub fn command(&self, rate: i8) -> &Command {
         self.commands.get(&rate).unwrap_or(&Command::ByPass)
     }
+
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or_else(num_cpus::get)
+    }
+
+    pub fn manifest_path(&self) -> Option<&Path> {
+        self.manifest.as_deref()
+    }
+
+    pub fn geo_filter(&self) -> Option<&GeoFilter> {
+        self.geo_filter.as_ref()
+    }
+
+    pub fn avif_threads(&self) -> usize {
+        self.avif_threads.unwrap_or(1)
+    }
+
+    pub fn output_template(&self) -> Option<&str> {
+        self.output_template.as_deref()
+    }
+
+    pub fn ignore_geotag(&self) -> bool {
+        self.ignore_geotag.unwrap_or(false)
+    }
+
+    pub fn listen_port(&self) -> Option<i32> {
+        self.listen_port
+    }
+
+    pub fn cred_path(&self) -> Option<&Path> {
+        self.cred_path.as_deref()
+    }
+
+    /// Switches the effective import paths, policies and credential path to
+    /// a named profile, so `Clone`/`Login`/`Clean` all operate against the
+    /// selected workflow instead of the top-level defaults. `None` and
+    /// `Some("default")` both keep the top-level values as-is, matching the
+    /// pre-profile behavior. Call this before `resolve`, so env/CLI
+    /// overrides still layer on top of whichever profile was selected.
+    pub fn select_profile(&mut self, profile: Option<&str>) -> Result<(), Error> {
+        let name = match profile {
+            None | Some("default") => return Ok(()),
+            Some(name) => name,
+        };
+
+        let selected = self.profiles.as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .ok_or_else(|| Error::Parse(format!("Unknown profile '{}'", name)))?;
+
+        if let Some(import) = &selected.import {
+            self.import = ImportPath { from: import.from.clone(), to: import.to.clone() };
+        }
+
+        if let Some(policies) = &selected.policies {
+            self.policies = policies.clone();
+
+            let mut m = BTreeMap::<i8, Command>::new();
+            for policy in self.policies.iter() {
+                for rate in policy.rate.iter() {
+                    let commands = policy.command().map_err(Error::Parse)?;
+                    m.insert(*rate, commands);
+                }
+            }
+            self.commands = m;
+        }
+
+        self.cred_path = Some(match &selected.cred_path {
+            Some(path) => path.clone(),
+            None => default_path().app_home().join(format!("cred-{}.json", name)),
+        });
+
+        Ok(())
+    }
+
+    /// Layers `KAPY_*` environment variables and then `cli` on top of
+    /// whatever the config file already set, for every key that has one:
+    /// default < config file < environment < CLI flag. Each key's winning
+    /// `Source` is recorded for `print_provenance`.
+    pub fn resolve(&mut self, cli: &CliOverrides) {
+        // import.from/import.to are mandatory in the config file, so there's
+        // no "default" tier for them
+        {
+            let mut value = self.import.from.clone();
+            let mut source = Source::ConfigFile;
+
+            if let Ok(v) = env::var("KAPY_IMPORT_FROM") {
+                value = PathBuf::from(v);
+                source = Source::Env;
+            }
+
+            if let Some(v) = &cli.import_from {
+                value = v.clone();
+                source = Source::Cli;
+            }
+
+            self.import.from = value;
+            self.provenance.insert("import_from".to_string(), source);
+        }
+
+        {
+            let mut value = self.import.to.clone();
+            let mut source = Source::ConfigFile;
+
+            if let Ok(v) = env::var("KAPY_IMPORT_TO") {
+                value = PathBuf::from(v);
+                source = Source::Env;
+            }
+
+            if let Some(v) = &cli.import_to {
+                value = v.clone();
+                source = Source::Cli;
+            }
+
+            self.import.to = value;
+            self.provenance.insert("import_to".to_string(), source);
+        }
+
+        {
+            let mut value = self.ignore_geotag.unwrap_or(false);
+            let mut source = if self.ignore_geotag.is_some() { Source::ConfigFile } else { Source::Default };
+
+            if let Ok(v) = env::var("KAPY_IGNORE_GEOTAG") {
+                value = v.eq_ignore_ascii_case("true") || v == "1";
+                source = Source::Env;
+            }
+
+            if let Some(v) = cli.ignore_geotag {
+                value = v;
+                source = Source::Cli;
+            }
+
+            self.ignore_geotag = Some(value);
+            self.provenance.insert("ignore_geotag".to_string(), source);
+        }
+
+        {
+            let mut value = self.listen_port;
+            let mut source = if self.listen_port.is_some() { Source::ConfigFile } else { Source::Default };
+
+            if let Ok(v) = env::var("KAPY_LISTEN_PORT") {
+                if let Ok(port) = v.parse::<i32>() {
+                    value = Some(port);
+                    source = Source::Env;
+                }
+            }
+
+            if let Some(v) = cli.listen_port {
+                value = Some(v);
+                source = Source::Cli;
+            }
+
+            self.listen_port = value;
+            self.provenance.insert("listen_port".to_string(), source);
+        }
+
+        {
+            let mut value = self.cred_path.clone();
+            let mut source = if self.cred_path.is_some() { Source::ConfigFile } else { Source::Default };
+
+            if let Ok(v) = env::var("KAPY_CRED_PATH") {
+                value = Some(PathBuf::from(v));
+                source = Source::Env;
+            }
+
+            if let Some(v) = &cli.cred_path {
+                value = Some(v.clone());
+                source = Source::Cli;
+            }
+
+            self.cred_path = value;
+            self.provenance.insert("cred_path".to_string(), source);
+        }
+
+        // policies have no env/CLI layer, only the config file defines them;
+        // recorded here so `print_provenance` shows the full picture
+        self.provenance.insert("policies".to_string(), Source::ConfigFile);
+    }
+
+    /// Prints the effective value and source of every layered config key,
+    /// for `clone --dry-run` to show where each setting actually came from.
+    pub fn print_provenance(&self) {
+        let source = |key: &str| self.provenance.get(key).copied().unwrap_or(Source::Default).as_str();
+
+        println!("Effective configuration:");
+        println!("  import.from = {} ({})", self.import.from.display(), source("import_from"));
+        println!("  import.to = {} ({})", self.import.to.display(), source("import_to"));
+        println!("  ignore_geotag = {} ({})", self.ignore_geotag(), source("ignore_geotag"));
+
+        if let Some(port) = self.listen_port {
+            println!("  listen_port = {} ({})", port, source("listen_port"));
+        }
+
+        if let Some(path) = &self.cred_path {
+            println!("  cred_path = {} ({})", path.display(), source("cred_path"));
+        }
+
+        println!("  policies = {} entries ({})", self.policies.len(), source("policies"));
+    }
+
+    /// The effective value and source of a single dotted config key, for the
+    /// `config get`/`config list` subcommands. Returns `None` for an unknown
+    /// key. Keys not layered through `resolve` (e.g. `workers`) report
+    /// `Source::ConfigFile` when set in the file, `Source::Default` otherwise.
+    pub fn get_value(&self, key: &str) -> Option<(String, Source)> {
+        let source = |k: &str| self.provenance.get(k).copied().unwrap_or(Source::Default);
+
+        Some(match key {
+            "import.from" => (self.import.from.display().to_string(), source("import_from")),
+            "import.to" => (self.import.to.display().to_string(), source("import_to")),
+            "ignore_geotag" => (self.ignore_geotag().to_string(), source("ignore_geotag")),
+            "listen_port" => (self.listen_port.map(|p| p.to_string()).unwrap_or_default(), source("listen_port")),
+            "cred_path" => (
+                self.cred_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+                source("cred_path"),
+            ),
+            "workers" => (
+                self.workers.map(|w| w.to_string()).unwrap_or_else(|| "auto".to_string()),
+                if self.workers.is_some() { Source::ConfigFile } else { Source::Default },
+            ),
+            "manifest" => (
+                self.manifest.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+                if self.manifest.is_some() { Source::ConfigFile } else { Source::Default },
+            ),
+            "avif_threads" => (
+                self.avif_threads().to_string(),
+                if self.avif_threads.is_some() { Source::ConfigFile } else { Source::Default },
+            ),
+            "output_template" => (
+                self.output_template.clone().unwrap_or_default(),
+                if self.output_template.is_some() { Source::ConfigFile } else { Source::Default },
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Every key `get_value` understands, in the same order `list` prints
+    /// them.
+    pub const KEYS: &'static [&'static str] = &[
+        "import.from", "import.to", "ignore_geotag", "listen_port", "cred_path",
+        "workers", "manifest", "avif_threads", "output_template",
+    ];
 }
 
 fn deserialize(s: String) -> Result<Config, Error> {
@@ -228,6 +637,56 @@ fn deserialize(s: String) -> Result<Config, Error> {
     }
 }
 
+/// Writes `value` into the YAML file at `path` under the dotted `key`
+/// (e.g. `import.to`, `workers`), for `kapy config set`. Edits the document
+/// as a generic `serde_yaml::Value` tree rather than through `Config`'s typed
+/// struct, so keys this binary doesn't model (or doesn't know about yet)
+/// round-trip untouched instead of being dropped.
+pub fn set_value_in_file(path: &Path, key: &str, value: &str) -> Result<(), Error> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::IO(e.to_string()))?;
+
+    let mut root: serde_yaml::Value = serde_yaml::from_str(&contents)
+        .map_err(|e| Error::Parse(e.to_string()))?;
+
+    let segments: Vec<&str> = key.split('.').collect();
+    set_nested(&mut root, &segments, parse_scalar(value))?;
+
+    let updated = serde_yaml::to_string(&root).map_err(|e| Error::Parse(e.to_string()))?;
+    fs::write(path, updated).map_err(|e| Error::IO(e.to_string()))
+}
+
+// interprets `value` the same way it would read if typed directly into the
+// YAML file, so `kapy config set workers 4` writes a number, not the string "4"
+fn parse_scalar(value: &str) -> serde_yaml::Value {
+    serde_yaml::from_str(value).unwrap_or_else(|_| serde_yaml::Value::String(value.to_string()))
+}
+
+fn set_nested(node: &mut serde_yaml::Value, segments: &[&str], value: serde_yaml::Value) -> Result<(), Error> {
+    let (head, rest) = segments.split_first()
+        .ok_or_else(|| Error::Parse("Empty config key".to_string()))?;
+
+    if !node.is_mapping() {
+        *node = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+    }
+
+    let key = serde_yaml::Value::String((*head).to_string());
+
+    if let serde_yaml::Value::Mapping(map) = node {
+        if rest.is_empty() {
+            map.insert(key, value);
+            return Ok(());
+        }
+
+        if !map.contains_key(&key) {
+            map.insert(key.clone(), serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+        }
+
+        return set_nested(map.get_mut(&key).unwrap(), rest, value);
+    }
+
+    unreachable!("node was just coerced into a mapping above")
+}
+
 #[derive(Debug)]
 pub enum Error {
     IO(String),
@@ -305,6 +764,7 @@ policies:
             resize: Resize::MPixels(36),
             format: Format::HEIC,
             quality: Quality::Percentage(92),
+            blurhash: None,
         });
     }
 
@@ -324,6 +784,42 @@ policies:
             resize: Resize::Percentage(50),
             format: Format::HEIC,
             quality: Quality::Percentage(90),
+            blurhash: None,
         })
     }
+
+    #[test]
+    fn get_policy_with_blurhash() {
+        let policy = Policy {
+            rate: vec![1],
+            command: Some(BTreeMap::from([
+                ("blurhash".to_string(), "4x3".to_string()),
+            ])),
+        };
+
+        let commands = policy.command().unwrap();
+        assert_eq!(commands, Command::Convert {
+            resize: Resize::Preserve,
+            format: Format::Preserve,
+            quality: Quality::Preserve,
+            blurhash: Some((4, 3)),
+        })
+    }
+
+    #[test]
+    fn format_round_trip() {
+        for format in [Format::JPEG, Format::HEIC, Format::AVIF, Format::WebP, Format::Preserve] {
+            let s = format.as_str();
+            assert_eq!(Format::from_str(s).unwrap(), format);
+        }
+    }
+
+    #[test]
+    fn parse_resize_grammar() {
+        assert_eq!(parse_resize("1920x1080").unwrap(), Resize::Box(1920, 1080));
+        assert_eq!(parse_resize("2048>").unwrap(), Resize::LongEdgeIfLarger(2048));
+        assert_eq!(parse_resize("w1600").unwrap(), Resize::Width(1600));
+        assert_eq!(parse_resize("h1200").unwrap(), Resize::Height(1200));
+        assert!(parse_resize("bogus").is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+use std::path::Path;
+use std::process;
+use clap::Subcommand;
+use console::style;
+
+use crate::config::Config;
+
+/// Operations available under `kapy config`. Kept as its own module (rather
+/// than folding into `config::mod`) since it's a CLI command handler like
+/// `clean`/`clone`/`login`, not part of the config-parsing model itself.
+#[derive(Subcommand, PartialEq, Debug)]
+pub enum ConfigAction {
+    /// Print the effective value and source of a single config key
+    Get {
+        /// Dotted key, e.g. 'import.from' or 'workers'
+        key: String,
+    },
+    /// Write a value into the config file, leaving every other key as-is
+    Set {
+        /// Dotted key, e.g. 'import.from' or 'workers'
+        key: String,
+        /// New value, parsed the same way it would if typed directly into the YAML file
+        value: String,
+    },
+    /// Dump every effective config key and its source
+    List,
+}
+
+pub fn do_config(conf_path: &Path, conf: &Config, action: &ConfigAction) {
+    match action {
+        ConfigAction::Get { key } => do_get(conf, key),
+        ConfigAction::Set { key, value } => do_set(conf_path, key, value),
+        ConfigAction::List => do_list(conf),
+    }
+}
+
+fn do_get(conf: &Config, key: &str) {
+    match conf.get_value(key) {
+        Some((value, source)) => println!("{} = {} ({})", key, value, source.as_str()),
+        None => {
+            eprintln!("Unknown config key '{}'", key);
+            process::exit(1);
+        }
+    }
+}
+
+fn do_list(conf: &Config) {
+    for key in Config::KEYS {
+        if let Some((value, source)) = conf.get_value(key) {
+            println!("{} = {} ({})", key, value, source.as_str());
+        }
+    }
+}
+
+fn do_set(conf_path: &Path, key: &str, value: &str) {
+    match crate::config::set_value_in_file(conf_path, key, value) {
+        Ok(()) => println!("{} {} = {}", style("Set").green().bold(), key, value),
+        Err(e) => {
+            eprintln!("Failed to set '{}': {:?}", key, e);
+            process::exit(1);
+        }
+    }
+}
@@ -4,19 +4,21 @@ use std::io::{BufRead, BufReader, Write};
 use std::ops::Add;
 use std::rc::Rc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
 use std::fmt::{Display, Formatter};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::{anyhow, Result};
-use oauth2::{AuthUrl, ClientSecret, CsrfToken, RedirectUrl, RevocationUrl, TokenUrl, Scope, PkceCodeChallenge, TokenResponse, AccessToken, AuthorizationCode, basic, revocation, RefreshToken};
+use oauth2::{AuthUrl, ClientSecret, CsrfToken, RedirectUrl, RevocationUrl, TokenUrl, DeviceAuthorizationUrl, Scope, PkceCodeChallenge, PkceCodeVerifier, TokenResponse, AccessToken, AuthorizationCode, basic, revocation, RefreshToken, RequestTokenError, EmptyExtraTokenFields};
 use oauth2::{basic::BasicClient, ClientId};
 use oauth2::basic::BasicTokenResponse;
+use oauth2::devicecode::StandardDeviceAuthorizationResponse;
 use oauth2::reqwest::http_client;
 use serde::{Deserialize, Serialize};
 use url::Url;
 use crate::config;
-use crate::drive::helper::FileCredentials;
+use crate::drive::helper;
+use crate::drive::helper::{CredentialStore, FileStore, KeyringStore};
 
 // This is a installed app, client secret for OAuth2 is an extension of client id
 // So, we can embed it
@@ -32,10 +34,24 @@ const OVERRIDE_CLIENT_SECRET: Option<&str> = option_env!("CLIENT_SECRET");
 const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const GOOGLE_TOKEN_URL: &str = "https://www.googleapis.com/oauth2/v3/token";
 const GOOGLE_REVOKE_URL: &str = "https://oauth2.googleapis.com/revoke";
+const GOOGLE_DEVICE_AUTH_URL: &str = "https://oauth2.googleapis.com/device/code";
 const GOOGLE_DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive";
+const GOOGLE_DEVICE_STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
 
 const DEFAULT_LISTEN_PORT: i32 = 18080;
 
+// how long the redirect server waits for the browser to finish sending the
+// callback request once it has connected, so a tab left open (or a request
+// that stalls mid-write) doesn't hang the whole command forever
+const REDIRECT_READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+// refresh a token this far ahead of its reported expiry, so clock skew and
+// request latency don't hand out a token that dies mid-request
+const TOKEN_EXPIRY_SKEW: Duration = Duration::from_secs(60);
+
+const TOKEN_RETRY_ATTEMPTS: u32 = 3;
+const TOKEN_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Token {
     client_id: String,
@@ -61,22 +77,33 @@ impl Token {
 
 type Client = oauth2::Client<basic::BasicErrorResponse, BasicTokenResponse, basic::BasicTokenType, basic::BasicTokenIntrospectionResponse, revocation::StandardRevocableToken, basic::BasicRevocationErrorResponse>;
 
+fn default_cred_path() -> Box<Path> {
+    let default_path = config::default_path();
+    let default_cred_path = Rc::clone(&default_path.cred_path());
+    default_cred_path.to_path_buf().into_boxed_path()
+}
+
+/// Selects which `CredentialStore` backs a `GoogleAuthenticator`.
 #[allow(dead_code)]
-pub enum CredPath<'a> {
-    Path(&'a Path),
-    DefaultPath,
+pub enum Store<'a> {
+    File(&'a Path),
+    DefaultFile,
+    Keyring,
 }
 
-impl<'a> CredPath<'a> {
-    fn path(&self) -> Box<Path> {
+impl<'a> Store<'a> {
+    fn build(&self) -> Box<dyn CredentialStore> {
         match self {
-            CredPath::Path(p) => {
-                p.to_path_buf().into_boxed_path()
-            }
-            CredPath::DefaultPath => {
-                let default_path = config::default_path();
-                let default_cred_path = Rc::clone(&default_path.cred_path());
-                default_cred_path.to_path_buf().into_boxed_path()
+            Store::File(p) => Box::new(FileStore::new(p.to_path_buf())),
+            Store::DefaultFile => Box::new(FileStore::new(default_cred_path().to_path_buf())),
+            Store::Keyring => {
+                match KeyringStore::new() {
+                    Ok(store) => Box::new(store),
+                    Err(e) => {
+                        eprintln!("{} falling back to file credential store", e);
+                        Box::new(FileStore::new(default_cred_path().to_path_buf()))
+                    }
+                }
             }
         }
     }
@@ -88,6 +115,19 @@ pub enum ListenPort {
     DefaultPort,
 }
 
+/// Selects how `GoogleAuthenticator::authenticate` obtains a fresh token when
+/// no (or an expired) one is on disk.
+#[allow(dead_code)]
+pub enum AuthFlow {
+    /// Opens a browser and receives the authorization code on a local loopback
+    /// redirect server. Requires a display and a bindable local port.
+    Browser,
+    /// RFC 8628 Device Authorization Grant: prints a verification URL and a
+    /// short user code to enter on another device, then polls Google for the
+    /// token. Suited to headless machines reached over SSH.
+    Device,
+}
+
 impl Display for ListenPort {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -111,12 +151,14 @@ pub struct GoogleAuthenticator {
     client_secret: String,
     client: Client,
     token: Rc<RefCell<Option<Token>>>,
-    cred_path: Rc<RefCell<PathBuf>>,
+    store: Box<dyn CredentialStore>,
     listen_port: i32,
+    auth_flow: AuthFlow,
 }
 
 impl GoogleAuthenticator {
-    pub fn new(listen_port: ListenPort, cred_path: CredPath) -> Self {
+    pub fn new(listen_port: ListenPort, store: Store, auth_flow: AuthFlow) -> Self {
+        let store = store.build();
         let auth_url = AuthUrl::new(GOOGLE_AUTH_URL.to_string()).unwrap();
         let token_url = TokenUrl::new(GOOGLE_TOKEN_URL.to_string()).unwrap();
 
@@ -136,9 +178,9 @@ impl GoogleAuthenticator {
             _ => String::from(OVERRIDE_CLIENT_SECRET.unwrap_or(DEFAULT_CLIENT_SECRET)),
         };
 
-        // try to read cred from file
+        // try to read cred from store
         let mut token = None;
-        if let Ok(t) = FileCredentials::read_file(&cred_path.path()) {
+        if let Ok(Some(t)) = store.read() {
             client_id = t.client_id.clone();
             client_secret = t.client_secret.clone();
             token = Some(t);
@@ -159,8 +201,9 @@ impl GoogleAuthenticator {
             client_secret,
             client,
             token: Rc::new(RefCell::new(token)),
-            cred_path: Rc::new(RefCell::new(cred_path.path().to_path_buf())),
+            store,
             listen_port: listen_port.port(),
+            auth_flow,
         }
     }
 
@@ -172,17 +215,29 @@ impl GoogleAuthenticator {
             let token = RefCell::borrow(&token);
 
             if let Some(t) = token.as_ref() {
-                // check access token expiration
-                let now = SystemTime::now();
-                let expires_at = t.created_at().add(t.token_response.expires_in().unwrap());
-
-                if now > expires_at {
-                    refresh = true;
-                    break;
+                match t.token_response.expires_in() {
+                    Some(expires_in) => {
+                        // refresh a little before the real expiry, so a token
+                        // that's about to die isn't handed out only to fail
+                        // mid-request
+                        let now = SystemTime::now().add(TOKEN_EXPIRY_SKEW);
+                        let expires_at = t.created_at().add(expires_in);
+
+                        if now > expires_at {
+                            refresh = true;
+                            break;
+                        }
+
+                        let ac = t.token_response.access_token();
+                        return Ok(ac.clone());
+                    }
+                    // the server didn't report a lifetime at all; don't trust
+                    // it to still be valid, refresh instead
+                    None => {
+                        refresh = true;
+                        break;
+                    }
                 }
-
-                let ac = t.token_response.access_token();
-                return Ok(ac.clone());
             }
 
             break;
@@ -213,8 +268,10 @@ impl GoogleAuthenticator {
 
         match refresh_token {
             Some(refresh_token) => {
-                let token_response = self.client.exchange_refresh_token(&refresh_token)
-                    .request(http_client);
+                let token_response = retry_token_request(|| {
+                    self.client.exchange_refresh_token(&refresh_token)
+                        .request(http_client)
+                });
 
                 match token_response {
                     Ok(mut token_response) => {
@@ -237,13 +294,30 @@ impl GoogleAuthenticator {
     }
 
     fn authenticate(&self) -> Result<AccessToken> {
+        match self.auth_flow {
+            AuthFlow::Browser => self.authenticate_browser(),
+            AuthFlow::Device => self.authenticate_device(),
+        }
+    }
+
+    fn authenticate_browser(&self) -> Result<AccessToken> {
         // create a PKCE code verifier and SHA-256 encode it as a code challenge
         let (pkce_code_challenge, pkce_code_verifier) =
             PkceCodeChallenge::new_random_sha256();
 
+        // bind the redirect server first: if the configured port is taken and
+        // it's the default one, this transparently falls back to an OS-assigned
+        // ephemeral port, so the actual port isn't known until now
+        let listener = bind_redirect_listener(self.listen_port)?;
+        let actual_port = listener.local_addr()?.port();
+
+        let redirect_url = RedirectUrl::new(format!("http://127.0.0.1:{}", actual_port)).unwrap();
+        let client = self.client.clone().set_redirect_uri(redirect_url);
+
         // generate authorization url
-        let (authorize_url, csrf_state) = self.client.authorize_url(CsrfToken::new_random)
+        let (authorize_url, csrf_state) = client.authorize_url(CsrfToken::new_random)
             .add_scope(Scope::new(GOOGLE_DRIVE_SCOPE.to_string()))
+            .add_scope(Scope::new(GOOGLE_DEVICE_STORAGE_SCOPE.to_string()))
             .set_pkce_challenge(pkce_code_challenge)
             .url();
 
@@ -253,17 +327,24 @@ impl GoogleAuthenticator {
         }
 
         // start simple redirect server to receive token information from OAuth2 server
-        match serve_redirect_oauth2(self.listen_port) {
+        match serve_redirect_oauth2(listener) {
             Ok((code, state)) => {
                 if state.secret() != csrf_state.secret() {
                     return Err(anyhow!("Not matched state '{}' != '{}'", state.secret(), csrf_state.secret()));
                 }
 
-                // Exchange the code with a token.
-                let token_response = self.client
-                    .exchange_code(code)
-                    .set_pkce_verifier(pkce_code_verifier)
-                    .request(http_client);
+                // Exchange the code with a token. The secrets are re-wrapped
+                // on each retry attempt since the request builders consume
+                // their verifier/code by value.
+                let code_secret = code.secret().clone();
+                let pkce_secret = pkce_code_verifier.secret().clone();
+
+                let token_response = retry_token_request(|| {
+                    client
+                        .exchange_code(AuthorizationCode::new(code_secret.clone()))
+                        .set_pkce_verifier(PkceCodeVerifier::new(pkce_secret.clone()))
+                        .request(http_client)
+                });
 
                 match token_response {
                     Ok(token_response) => {
@@ -283,6 +364,103 @@ impl GoogleAuthenticator {
         }
     }
 
+    fn authenticate_device(&self) -> Result<AccessToken> {
+        let device_auth_url = DeviceAuthorizationUrl::new(GOOGLE_DEVICE_AUTH_URL.to_string()).unwrap();
+        let client = self.client.clone().set_device_authorization_url(device_auth_url);
+
+        // ask Google for a device code / user code pair
+        let details: StandardDeviceAuthorizationResponse = client
+            .exchange_device_code()
+            .map_err(|e| anyhow!("Failed to build device authorization request: {}", e.to_string()))?
+            .add_scope(Scope::new(GOOGLE_DRIVE_SCOPE.to_string()))
+            .add_scope(Scope::new(GOOGLE_DEVICE_STORAGE_SCOPE.to_string()))
+            .request(http_client)
+            .map_err(|e| anyhow!("Failed to request device code: {}", e.to_string()))?;
+
+        println!("To authenticate, visit {} and enter the code: {}",
+                 details.verification_uri().to_string(), details.user_code().secret());
+
+        // poll the token endpoint at the server-specified interval; the oauth2
+        // crate keeps polling on 'authorization_pending', backs off on
+        // 'slow_down' and gives up on 'expired_token'/'access_denied'
+        let token_response = client
+            .exchange_device_access_token(&details)
+            .request(http_client, std::thread::sleep, None)
+            .map_err(|e| anyhow!("Failed to exchange device code to access token: {}", e.to_string()))?;
+
+        let ac = token_response.access_token().clone();
+        self.set_token(token_response);
+
+        Ok(ac)
+    }
+
+    /// Revokes the stored token at Google and forgets it locally, both in
+    /// memory and in the credential store. A missing token is a no-op
+    /// success, since there is nothing left to disconnect.
+    pub fn revoke(&self) -> Result<()> {
+        let revocable_token = {
+            let token = Rc::clone(&self.token);
+            let token = RefCell::borrow(&token);
+
+            match token.as_ref() {
+                Some(t) => {
+                    match t.token_response.refresh_token() {
+                        Some(refresh_token) => Some(revocation::StandardRevocableToken::RefreshToken(refresh_token.clone())),
+                        None => Some(revocation::StandardRevocableToken::AccessToken(t.token_response.access_token().clone())),
+                    }
+                }
+                None => None,
+            }
+        };
+
+        let revocable_token = match revocable_token {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        self.client.revoke_token(revocable_token)
+            .map_err(|e| anyhow!("Failed to build revoke request: {}", e.to_string()))?
+            .request(http_client)
+            .map_err(|e| anyhow!("Failed to revoke token: {}", e.to_string()))?;
+
+        // forget the token locally now that Google has revoked the grant
+        let t = Rc::clone(&self.token);
+        let mut t = RefCell::borrow_mut(&t);
+        *t = None;
+
+        self.store.remove()
+    }
+
+    /// Seeds the stored token from a value obtained out-of-band instead of
+    /// through the browser or device flow: either the base64-encoded JSON
+    /// blob this module itself writes (copied from another machine's
+    /// credential file), or a bare OAuth2 refresh token. No access token is
+    /// minted here; `access_token()` sees the unknown expiry and refreshes
+    /// before its first real use, which also validates the supplied value.
+    pub fn import_token(&self, input: &str) -> Result<()> {
+        let token = match helper::unmarshal(input.as_bytes().to_vec()) {
+            Ok(token) => token,
+            Err(_) => {
+                let mut token_response = BasicTokenResponse::new(
+                    AccessToken::new(String::new()),
+                    basic::BasicTokenType::Bearer,
+                    EmptyExtraTokenFields {},
+                );
+                token_response.set_refresh_token(Some(RefreshToken::new(input.to_string())));
+
+                Token::new(&self.client_id, &self.client_secret, token_response, SystemTime::now())
+            }
+        };
+
+        self.store.write(&token)?;
+
+        let t = Rc::clone(&self.token);
+        let mut t = RefCell::borrow_mut(&t);
+        *t = Some(token);
+
+        Ok(())
+    }
+
     fn set_token(&self, token_response: BasicTokenResponse) {
         let t = Rc::clone(&self.token);
         let mut t = RefCell::borrow_mut(&t);
@@ -291,12 +469,9 @@ impl GoogleAuthenticator {
         // make token
         let token = Token::new(&self.client_id, &self.client_secret, token_response, now);
 
-        // write to cred path
-        let cred_path = Rc::clone(&self.cred_path);
-        let cred_path = RefCell::borrow(&cred_path);
-
-        if let Err(e) = FileCredentials::write_file(&token, cred_path.as_path()) {
-            eprintln!("Failed to write cred file: {}", e);
+        // write to credential store
+        if let Err(e) = self.store.write(&token) {
+            eprintln!("Failed to write credential store: {}", e);
         }
 
         // set token
@@ -304,75 +479,150 @@ impl GoogleAuthenticator {
     }
 }
 
-fn serve_redirect_oauth2(listen_port: i32) -> Result<(AuthorizationCode, CsrfToken)> {
+// retries a token request with doubling delays, but only for transient
+// transport-level failures; a response the server actually sent back (e.g.
+// `invalid_grant`) is permanent and surfaces immediately instead of being
+// retried into a confusing delay
+fn retry_token_request<T, RE, F>(mut request: F) -> std::result::Result<T, RequestTokenError<RE, basic::BasicErrorResponse>>
+    where
+        RE: std::error::Error + 'static,
+        F: FnMut() -> std::result::Result<T, RequestTokenError<RE, basic::BasicErrorResponse>>,
+{
+    let mut delay = TOKEN_RETRY_BASE_DELAY;
+
+    for attempt in 1..=TOKEN_RETRY_ATTEMPTS {
+        match request() {
+            Ok(response) => return Ok(response),
+            Err(e) => {
+                let transient = matches!(e, RequestTokenError::Request(_));
+
+                if !transient || attempt == TOKEN_RETRY_ATTEMPTS {
+                    return Err(e);
+                }
+
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+// binds the loopback redirect server, falling back to an OS-assigned
+// ephemeral port when the default port is already taken by something else
+// on the machine (e.g. a stale kapy process or an unrelated service)
+fn bind_redirect_listener(listen_port: i32) -> Result<TcpListener> {
     let listen_addr = format!("127.0.0.1:{}", listen_port);
 
-    let listener = match TcpListener::bind(&listen_addr) {
-        Ok(l) => l,
+    match TcpListener::bind(&listen_addr) {
+        Ok(l) => Ok(l),
+        Err(e) if listen_port == DEFAULT_LISTEN_PORT => {
+            TcpListener::bind("127.0.0.1:0")
+                .map_err(|e2| anyhow!("Failed to listen at '{}' ({}), and failed to fall back to an ephemeral port: {}", &listen_addr, e, e2.to_string()))
+        }
         Err(e) => {
-            return Err(anyhow!("Failed to listen at '{}': {}", &listen_addr, e.to_string()));
+            Err(anyhow!("Failed to listen at '{}': {}", &listen_addr, e.to_string()))
         }
-    };
+    }
+}
+
+fn write_html_response(stream: &mut TcpStream, status_line: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\ncontent-length: {}\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body
+    );
 
+    stream.write_all(response.as_bytes())
+}
+
+const SUCCESS_PAGE: &str = "<!DOCTYPE html><html><head><title>kapy</title></head>\
+<body><h1>Login succeeded</h1><p>You can close this window and return to the terminal.</p></body></html>";
+
+fn failure_page(reason: &str) -> String {
+    format!("<!DOCTYPE html><html><head><title>kapy</title></head>\
+<body><h1>Login failed</h1><p>{}</p><p>You can close this window and return to the terminal.</p></body></html>", reason)
+}
+
+fn serve_redirect_oauth2(listener: TcpListener) -> Result<(AuthorizationCode, CsrfToken)> {
     for stream in listener.incoming() {
         if let Ok(mut stream) = stream {
-            let code;
-            let state;
-            {
+            if let Err(e) = stream.set_read_timeout(Some(REDIRECT_READ_TIMEOUT)) {
+                return Err(anyhow!("Failed to set read timeout on redirect connection: {}", e.to_string()));
+            }
+
+            let request_line = {
                 let mut reader = BufReader::new(&stream);
 
                 let mut request_line = String::new();
                 if let Err(e) = reader.read_line(&mut request_line) {
-                    return Err(anyhow!("Failed to read line from stream: {}", e.to_string()));
+                    return Err(anyhow!("Failed to read the OAuth2 redirect: {}", e.to_string()));
                 }
 
-                let redirect_url = match request_line.split_whitespace().nth(1) {
-                    Some(s) => s,
-                    None => {
-                        return Err(anyhow!("Invalid request line '{}'", request_line));
-                    }
-                };
+                request_line
+            };
 
-                let url = Url::parse(&("http://localhost".to_string() + redirect_url)).unwrap();
+            let redirect_url = match request_line.split_whitespace().nth(1) {
+                Some(s) => s,
+                None => {
+                    return Err(anyhow!("Invalid request line '{}'", request_line));
+                }
+            };
 
-                let code_pair = match url.query_pairs()
-                    .find(|pair| {
-                        let &(ref key, _) = pair;
-                        key == "code"
-                    }) {
-                    Some(p) => p,
-                    None => {
-                        return Err(anyhow!("Can't find code pair on '{}'", url.to_string()));
-                    }
-                };
+            let url = Url::parse(&("http://localhost".to_string() + redirect_url)).unwrap();
 
-                let (_, value) = code_pair;
-                code = AuthorizationCode::new(value.into_owned());
+            // a denied consent prompt redirects back with `error=access_denied`
+            // and no `code` at all, so check for that before looking for a code
+            if let Some((_, error)) = url.query_pairs().find(|(key, _)| key == "error") {
+                let error = error.into_owned();
 
-                let state_pair = match url.query_pairs()
-                    .find(|pair| {
-                        let &(ref key, _) = pair;
-                        key == "state"
-                    }) {
-                    Some(p) => p,
-                    None => {
-                        return Err(anyhow!("Can't find state pair on '{}'", url.to_string()));
-                    }
-                };
+                if let Err(e) = write_html_response(&mut stream, "400 Bad Request", &failure_page(&error)) {
+                    eprintln!("Failed to write to browser, but it's OK: {}", e.to_string());
+                }
 
-                let (_, value) = state_pair;
-                state = CsrfToken::new(value.into_owned());
+                return Err(anyhow!("Google denied the authorization request: {}", error));
             }
 
+            let code_pair = match url.query_pairs()
+                .find(|pair| {
+                    let &(ref key, _) = pair;
+                    key == "code"
+                }) {
+                Some(p) => p,
+                None => {
+                    if let Err(e) = write_html_response(&mut stream, "400 Bad Request", &failure_page("Missing authorization code")) {
+                        eprintln!("Failed to write to browser, but it's OK: {}", e.to_string());
+                    }
+
+                    return Err(anyhow!("Can't find code pair on '{}'", url.to_string()));
+                }
+            };
+
+            let (_, value) = code_pair;
+            let code = AuthorizationCode::new(value.into_owned());
+
+            let state_pair = match url.query_pairs()
+                .find(|pair| {
+                    let &(ref key, _) = pair;
+                    key == "state"
+                }) {
+                Some(p) => p,
+                None => {
+                    if let Err(e) = write_html_response(&mut stream, "400 Bad Request", &failure_page("Missing state parameter")) {
+                        eprintln!("Failed to write to browser, but it's OK: {}", e.to_string());
+                    }
+
+                    return Err(anyhow!("Can't find state pair on '{}'", url.to_string()));
+                }
+            };
+
+            let (_, value) = state_pair;
+            let state = CsrfToken::new(value.into_owned());
+
             // respond to browser
-            let message = "Good! You turn off this window any time! :)";
-            let response = format!(
-                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
-                message.len(),
-                message
-            );
-
-            if let Err(e) = stream.write_all(response.as_bytes()) {
+            if let Err(e) = write_html_response(&mut stream, "200 OK", SUCCESS_PAGE) {
                 eprintln!("Failed to write to browser, but it's OK: {}", e.to_string());
             }
 
@@ -388,12 +638,10 @@ fn serve_redirect_oauth2(listen_port: i32) -> Result<(AuthorizationCode, CsrfTok
 mod tests {
     use super::*;
     use oauth2::basic::BasicTokenType;
-    use oauth2::EmptyExtraTokenFields;
-    use crate::drive::helper::FileCredentials;
 
     #[test]
     fn google_oauth2() {
-        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, CredPath::DefaultPath);
+        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::DefaultFile, AuthFlow::Browser);
 
         // get access token with login
         let ac = auth.access_token().unwrap();
@@ -412,11 +660,11 @@ mod tests {
         let token = Token::new("client_id", "client_secret", token_response, SystemTime::now());
 
         // marshal token
-        let marshaled = FileCredentials::marshal(&token).unwrap();
+        let marshaled = helper::marshal(&token).unwrap();
         println!("marshaled = {}", marshaled);
 
         // unmarshal token
-        let unmarshaled_token = FileCredentials::unmarshal(marshaled.into_bytes()).unwrap();
+        let unmarshaled_token = helper::unmarshal(marshaled.into_bytes()).unwrap();
 
         // comparison values
         assert_eq!(token.client_id, "client_id");
@@ -1,41 +1,62 @@
 use std::fs;
 use std::fs::OpenOptions;
 use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::path::PathBuf;
 use base64::{{Engine as _, engine::general_purpose}};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use crate::drive::auth::Token;
 
 #[cfg(unix)]
 use std::os::unix::fs::OpenOptionsExt;
 
-pub struct FileCredentials;
+/// Where a `Token` is persisted between runs. Implementations are swapped in
+/// behind `GoogleAuthenticator` so the same authenticate/refresh logic works
+/// whether the token ends up on the OS secret store or a plain file.
+pub trait CredentialStore {
+    fn read(&self) -> Result<Option<Token>>;
+    fn write(&self, token: &Token) -> Result<()>;
+    fn remove(&self) -> Result<()>;
+}
 
-impl FileCredentials {
-    pub fn marshal(token: &Token) -> Result<String> {
-        let json = serde_json::to_string(token)?;
+pub fn marshal(token: &Token) -> Result<String> {
+    let json = serde_json::to_string(token)?;
 
-        // base64 encoding
-        Ok(general_purpose::STANDARD_NO_PAD.encode(json.as_bytes()))
-    }
+    // base64 encoding
+    Ok(general_purpose::STANDARD_NO_PAD.encode(json.as_bytes()))
+}
+
+pub fn unmarshal(input: Vec<u8>) -> Result<Token> {
+    // base64 decoding
+    let bytes = general_purpose::STANDARD_NO_PAD.decode(input)?;
 
-    pub fn unmarshal(input: Vec<u8>) -> Result<Token> {
-        // base64 decoding
-        let bytes = general_purpose::STANDARD_NO_PAD.decode(input)?;
+    // unmarshal to struct
+    let json = String::from_utf8(bytes)?;
+    let token = serde_json::from_str::<Token>(&json)?;
+    Ok(token)
+}
+
+/// Persists the token as base64-encoded JSON in a single file, `0600` on unix.
+pub struct FileStore {
+    path: PathBuf,
+}
 
-        // unmarshal to struct
-        let json = String::from_utf8(bytes)?;
-        let token = serde_json::from_str::<Token>(&json)?;
-        Ok(token)
+impl FileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
     }
+}
 
-    pub fn read_file(path: &Path) -> Result<Token> {
-        let bytes = fs::read(path)?;
-        FileCredentials::unmarshal(bytes)
+impl CredentialStore for FileStore {
+    fn read(&self) -> Result<Option<Token>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(unmarshal(bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub fn write_file(token: &Token, path: &Path) -> Result<()> {
-        let encoded = FileCredentials::marshal(token)?;
+    fn write(&self, token: &Token) -> Result<()> {
+        let encoded = marshal(token)?;
 
         let file;
 
@@ -46,7 +67,7 @@ impl FileCredentials {
                 .create(true)
                 .truncate(true)
                 .mode(0o600)
-                .open(path);
+                .open(&self.path);
         }
 
         #[cfg(windows)]
@@ -55,11 +76,63 @@ impl FileCredentials {
                 .write(true)
                 .create(true)
                 .truncate(true)
-                .open(path);
+                .open(&self.path);
         }
 
         let file = file?;
         let mut writer = BufWriter::new(file);
         Ok(writer.write_all(encoded.as_bytes())?)
     }
+
+    fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+const KEYRING_SERVICE: &str = "kapy";
+const KEYRING_ACCOUNT: &str = "google-drive-token";
+
+/// Persists the token in the OS secret store (Keychain / Secret Service /
+/// Credential Manager) via the `keyring` crate, so a refresh token never
+/// touches disk as plaintext.
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+impl KeyringStore {
+    pub fn new() -> Result<Self> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
+            .map_err(|e| anyhow!("Failed to open OS credential store: {}", e))?;
+
+        Ok(Self { entry })
+    }
+}
+
+impl CredentialStore for KeyringStore {
+    fn read(&self) -> Result<Option<Token>> {
+        match self.entry.get_password() {
+            Ok(encoded) => Ok(Some(unmarshal(encoded.into_bytes())?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow!("Failed to read token from OS credential store: {}", e)),
+        }
+    }
+
+    fn write(&self, token: &Token) -> Result<()> {
+        let encoded = marshal(token)?;
+
+        self.entry.set_password(&encoded)
+            .map_err(|e| anyhow!("Failed to write token to OS credential store: {}", e))
+    }
+
+    fn remove(&self) -> Result<()> {
+        match self.entry.delete_password() {
+            Ok(_) => Ok(()),
+            Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to remove token from OS credential store: {}", e)),
+        }
+    }
 }
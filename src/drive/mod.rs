@@ -1,4 +1,5 @@
 pub mod auth;
+pub mod sync;
 mod helper;
 
 use std::collections::HashMap;
@@ -30,6 +31,10 @@ impl GoogleDrive {
         let page_size_str = format!("{}", page_size);
         params.insert("pageSize", page_size_str);
 
+        // the API only returns `kind`/`id`/`name`/`mimeType` unless asked
+        // for more, so spell out every field `FileMetadata` carries
+        params.insert("fields", String::from("nextPageToken, incompleteSearch, files(kind, id, name, mimeType, modifiedTime, md5Checksum)"));
+
         if let Some(page_token) = next_page_token {
             params.insert("pageToken", String::from(page_token));
         }
@@ -111,6 +116,8 @@ pub struct FileMetadata {
     pub id: String,
     pub name: String,
     pub mime_type: String,
+    pub modified_time: Option<String>,
+    pub md5_checksum: Option<String>,
 }
 
 #[cfg(test)]
@@ -119,7 +126,7 @@ mod tests {
     use std::collections::HashMap;
     use chrono::Utc;
     use url::form_urlencoded;
-    use crate::drive::auth::{CredPath, ListenPort};
+    use crate::drive::auth::{AuthFlow, ListenPort, Store};
 
     #[test]
     fn build_url_param() {
@@ -163,7 +170,7 @@ mod tests {
 
     #[test]
     fn list_google_drive() {
-        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, CredPath::DefaultPath);
+        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::DefaultFile, AuthFlow::Browser);
         let drive = GoogleDrive::new(auth);
 
         let created_at = Utc::now();
@@ -179,7 +186,7 @@ mod tests {
 
     #[test]
     fn download_blob_from_google_drive() {
-        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, CredPath::DefaultPath);
+        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::DefaultFile, AuthFlow::Browser);
         let drive = GoogleDrive::new(auth);
 
         let file_id = "1lNuJCNkXjrUkJIDF6gKlVztQkvNPrnx-";
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::drive::GoogleDrive;
+
+const MANIFEST_FILENAME: &str = "drive-sync.json";
+
+// file id -> what was downloaded for it last time, so a later run can tell
+// an unchanged file apart from one that needs re-fetching
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SyncEntry {
+    modified_time: String,
+    md5_checksum: Option<String>,
+    local_path: PathBuf,
+}
+
+// persisted at `<app_home>/drive-sync.json`, alongside config.yaml and the
+// credential file, so a sync can resume or skip unchanged files across runs
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncManifest {
+    // RFC3339 timestamp this manifest's sync last completed at; drives the
+    // `modifiedTime > ...` filter on the next run
+    last_synced: Option<String>,
+    entries: HashMap<String, SyncEntry>,
+}
+
+impl SyncManifest {
+    fn load(path: &Path) -> Result<SyncManifest> {
+        if !path.exists() {
+            return Ok(SyncManifest::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)?;
+
+        Ok(())
+    }
+
+    fn is_unchanged(&self, file_id: &str, modified_time: &str, md5_checksum: &Option<String>) -> bool {
+        self.entries.get(file_id)
+            .map_or(false, |entry| entry.modified_time == modified_time && &entry.md5_checksum == md5_checksum)
+    }
+}
+
+// downloads every file matching `q` that's new or changed since the last
+// sync, writing each blob to `dest_dir` under its drive file name. Progress
+// (file id, `modifiedTime`, `md5Checksum`, local path) is recorded in a
+// manifest under `app_home`, saved after every file rather than only at the
+// end, so an interrupted run resumes from there instead of re-downloading
+// items that already completed. Pass `full: true` to ignore the manifest
+// entirely and re-download everything matched by `q`. Returns the number of
+// files actually downloaded.
+pub fn sync<F>(drive: &GoogleDrive, app_home: &Path, dest_dir: &Path,
+               q: &str, page_size: usize, full: bool,
+               mut when_downloading: F) -> Result<usize>
+    where
+        F: FnMut(&str)
+{
+    let manifest_path = app_home.join(MANIFEST_FILENAME);
+    let mut manifest = if full {
+        SyncManifest::default()
+    } else {
+        SyncManifest::load(&manifest_path)?
+    };
+
+    let q = match (&manifest.last_synced, full) {
+        (Some(last_synced), false) => format!("({}) and modifiedTime > '{}'", q, last_synced),
+        _ => q.to_string(),
+    };
+
+    fs::create_dir_all(dest_dir)?;
+
+    let sync_started_at = chrono::Utc::now().to_rfc3339();
+    let mut downloaded = 0;
+    let mut next_page_token: Option<String> = None;
+
+    'paging: loop {
+        let response = drive.list(&q, page_size, next_page_token.as_deref())?;
+
+        for file in response.files.iter() {
+            let modified_time = file.modified_time.clone().unwrap_or_default();
+
+            if manifest.is_unchanged(&file.id, &modified_time, &file.md5_checksum) {
+                continue;
+            }
+
+            when_downloading(&file.name);
+
+            let blob = drive.download_blob(&file.id)?;
+            let local_path = dest_dir.join(&file.name);
+            fs::write(&local_path, &blob)?;
+
+            manifest.entries.insert(file.id.clone(), SyncEntry {
+                modified_time,
+                md5_checksum: file.md5_checksum.clone(),
+                local_path,
+            });
+            downloaded += 1;
+
+            // save as we go, so a run interrupted partway through doesn't
+            // have to redo files it already finished
+            manifest.save(&manifest_path)?;
+        }
+
+        match response.next_page_token {
+            Some(token) => next_page_token = Some(token),
+            None => break 'paging,
+        }
+    }
+
+    manifest.last_synced = Some(sync_started_at);
+    manifest.save(&manifest_path)?;
+
+    Ok(downloaded)
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const STATE_FILENAME: &str = ".kapy-state";
+const STATE_VERSION: u32 = 1;
+
+// per-source-file identity this state was built from: relative path, size,
+// and a truncated mtime. Borrowed from Mercurial's dirstate: only the low 31
+// bits of the mtime's seconds are kept, plus the nanosecond remainder, so
+// comparisons stay stable across filesystems that round or drop precision.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct EntryState {
+    size: u64,
+    mtime_secs: u32,
+    mtime_nanos: u32,
+}
+
+// a dirstate-style cache of which source files have already been imported,
+// persisted at `<import_to>/.kapy-state`; `do_clone` still walks the whole
+// source tree on every run, but skips inspecting and cloning files whose
+// (relative path, size, mtime) is already recorded here
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportState {
+    version: u32,
+
+    // truncated mtime seconds as of the last `save`; an entry whose own
+    // mtime lands on or after this is treated as ambiguous and re-imported,
+    // since a file saved in the same second this state was written could
+    // share an old mtime by coincidence
+    written_at_secs: u32,
+
+    entries: HashMap<String, EntryState>,
+}
+
+impl ImportState {
+    // loads the state file under `import_to`, or an empty one if it doesn't
+    // exist yet (e.g. the very first clone into this destination)
+    pub fn load(import_to: &Path) -> Result<ImportState> {
+        let path = import_to.join(STATE_FILENAME);
+
+        if !path.exists() {
+            return Ok(ImportState {
+                version: STATE_VERSION,
+                written_at_secs: 0,
+                entries: HashMap::new(),
+            });
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        let state: ImportState = serde_json::from_str(&contents)?;
+
+        if state.version != STATE_VERSION {
+            return Err(anyhow!("Incompatible import state version {} in '{}' (expected {}); remove it to force a full reimport",
+                state.version, path.to_str().unwrap(), STATE_VERSION));
+        }
+
+        Ok(state)
+    }
+
+    pub fn save(&mut self, import_to: &Path) -> Result<()> {
+        self.written_at_secs = truncate(SystemTime::now()).0;
+
+        let path = import_to.join(STATE_FILENAME);
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)?;
+
+        Ok(())
+    }
+
+    // true if `rel_path` was already imported with this exact (size, mtime)
+    // identity, and is therefore safe to skip this run
+    pub fn already_imported(&self, rel_path: &str, size: u64, mtime: SystemTime) -> bool {
+        let (mtime_secs, mtime_nanos) = truncate(mtime);
+
+        if mtime_secs >= self.written_at_secs {
+            return false;
+        }
+
+        self.entries.get(rel_path)
+            .map_or(false, |e| *e == EntryState { size, mtime_secs, mtime_nanos })
+    }
+
+    pub fn mark_imported(&mut self, rel_path: String, size: u64, mtime: SystemTime) {
+        let (mtime_secs, mtime_nanos) = truncate(mtime);
+        self.entries.insert(rel_path, EntryState { size, mtime_secs, mtime_nanos });
+    }
+}
+
+fn truncate(t: SystemTime) -> (u32, u32) {
+    let dur = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    ((dur.as_secs() as u32) & 0x7fff_ffff, dur.subsec_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn skips_matching_entry_and_catches_changes() {
+        let mut state = ImportState {
+            version: STATE_VERSION,
+            written_at_secs: 2_000,
+            entries: HashMap::new(),
+        };
+
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_000);
+        state.mark_imported("2023/img.jpg".to_string(), 1234, mtime);
+
+        assert!(state.already_imported("2023/img.jpg", 1234, mtime));
+        assert!(!state.already_imported("2023/img.jpg", 9999, mtime));
+        assert!(!state.already_imported("2023/img.jpg", 1234, mtime + Duration::from_secs(1)));
+        assert!(!state.already_imported("2023/other.jpg", 1234, mtime));
+    }
+
+    #[test]
+    fn treats_entry_at_or_after_write_time_as_ambiguous() {
+        let mut state = ImportState {
+            version: STATE_VERSION,
+            written_at_secs: 2_000,
+            entries: HashMap::new(),
+        };
+
+        let mtime = UNIX_EPOCH + Duration::from_secs(2_000);
+        state.mark_imported("same-second.jpg".to_string(), 1234, mtime);
+
+        assert!(!state.already_imported("same-second.jpg", 1234, mtime));
+    }
+
+    #[test]
+    fn rejects_future_version() {
+        let json = serde_json::to_string(&ImportState {
+            version: STATE_VERSION + 1,
+            written_at_secs: 0,
+            entries: HashMap::new(),
+        }).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("kapy-state-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(STATE_FILENAME), json).unwrap();
+
+        assert!(ImportState::load(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
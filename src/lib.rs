@@ -1,19 +1,25 @@
 mod init;
 mod clean;
 mod clone;
+mod import_state;
+mod prune;
 
 mod config;
+mod config_cmd;
 mod processor;
 mod drive;
 mod progress;
 mod login;
+mod sync_cmd;
 
 use std::path::PathBuf;
 use std::process;
 
 use clap::{Parser, Subcommand};
-use crate::config::Config;
-use crate::drive::auth::ListenPort;
+use crate::clone::MessageFormat;
+use crate::config::{CliOverrides, Config};
+use crate::config_cmd::ConfigAction;
+use crate::drive::auth::{AuthFlow, ListenPort};
 
 #[derive(Parser)]
 #[command(author, version, about = "A copy utility for large images taken by cameras", long_about = None)]
@@ -26,6 +32,11 @@ struct Cli {
     #[arg(long, value_name = "CRED_PATH", global = true)]
     cred: Option<PathBuf>,
 
+    /// Select a named profile section from the config file, for running
+    /// against a separate destination/credential set (e.g. personal vs. client)
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -43,10 +54,32 @@ enum Commands {
         #[arg(long, value_name = "TO_PATH")]
         to: Option<PathBuf>,
 
+        /// Number of worker threads to inspect and clone images with (defaults to the number of logical cores)
+        #[arg(short, long, value_name = "JOBS")]
+        jobs: Option<usize>,
+
         /// Set ignore geotag
         #[arg(long, default_value_t = false)]
         ignore_geotag: bool,
 
+        /// Geotag against a local GPX track file instead of searching Google
+        /// Drive for one
+        #[arg(long, value_name = "GPX_PATH", conflicts_with = "location_history")]
+        gpx_file: Option<PathBuf>,
+
+        /// Geotag against a Google Takeout 'Records.json' location history
+        /// export instead of searching Google Drive for a GPX track
+        #[arg(long, value_name = "HISTORY_PATH", conflicts_with = "gpx_file")]
+        location_history: Option<PathBuf>,
+
+        /// Geotag each photo by matching its own 'DateTimeOriginal' directly
+        /// against this GPX track (camera-clock-aware, interpolates between
+        /// track points and snaps to the track's first/last point within
+        /// tolerance), instead of the bucketed GPS-track search
+        /// used by --gpx-file/--location-history
+        #[arg(long, value_name = "GPX_PATH")]
+        geotag_gpx: Option<PathBuf>,
+
         /// Show what would do without copying/writing to destination
         #[arg(long, default_value_t = false)]
         dry_run: bool,
@@ -54,6 +87,16 @@ enum Commands {
         /// Import after specific date (YYYY-MM-DD or YYYY-MM or YYYY)
         #[arg(long, value_name = "AFTER")]
         after: Option<String>,
+
+        /// Print the full source -> destination mapping and ask for confirmation before cloning
+        #[arg(long, default_value_t = false)]
+        plan: bool,
+
+        /// Report progress as JSON events instead of the human progress
+        /// display, for scripts/GUIs to consume ('json' pretty-prints each
+        /// event, 'json-compact' prints one event per line)
+        #[arg(long, value_enum, default_value = "human")]
+        message_format: MessageFormat,
     },
     /// Initialize to make configuration file
     Init {
@@ -67,10 +110,71 @@ enum Commands {
         /// Listen port to exchange token for OAuth2.0
         #[arg(short, long)]
         listen_port: Option<i32>,
+
+        /// Authenticate via the device authorization flow instead of opening a
+        /// browser, for headless machines reached over SSH
+        #[arg(long, default_value_t = false)]
+        device: bool,
+
+        /// Import a pre-obtained OAuth2 refresh token or full credential blob
+        /// instead of running the interactive login flow. Reads from FILE, or
+        /// from stdin when FILE is '-' or omitted
+        #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+        token: Option<String>,
     },
 
-    /// Clean credentials
+    /// Revoke the stored google drive grant and forget the credentials
+    #[command(visible_aliases = ["logout", "disconnect"])]
     Clean,
+
+    /// Apply retention to the destination library, removing dated buckets beyond what's kept
+    Prune {
+        /// Number of most recent days to keep
+        #[arg(long, value_name = "N")]
+        keep_daily: Option<u32>,
+
+        /// Number of most recent ISO weeks to keep
+        #[arg(long, value_name = "N")]
+        keep_weekly: Option<u32>,
+
+        /// Number of most recent months to keep
+        #[arg(long, value_name = "N")]
+        keep_monthly: Option<u32>,
+
+        /// Number of most recent years to keep
+        #[arg(long, value_name = "N")]
+        keep_yearly: Option<u32>,
+
+        /// Show what would be removed without deleting anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Inspect or edit settings in the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Incrementally download every file matching a Google Drive query into a
+    /// local directory, resuming from where the last sync left off
+    Sync {
+        /// Drive search query, e.g. "mimeType='application/gpx+xml'"
+        #[arg(long, value_name = "QUERY")]
+        query: String,
+
+        /// Destination directory to download into
+        #[arg(long, value_name = "DEST_PATH")]
+        to: PathBuf,
+
+        /// Ignore the sync manifest and re-download every matching file
+        #[arg(long, default_value_t = false)]
+        full: bool,
+
+        /// Drive API page size
+        #[arg(long, default_value_t = 100)]
+        page_size: usize,
+    },
 }
 
 pub fn run() {
@@ -96,30 +200,88 @@ pub fn run() {
         process::exit(1);
     });
 
-    let cred_path = cli.cred.as_deref().unwrap_or(default_cred_path.as_ref());
+    conf.select_profile(cli.profile.as_deref()).unwrap_or_else(|err| {
+        eprintln!("Failed to select profile: {:?}", err);
+        process::exit(1);
+    });
+
+    // layer in KAPY_* environment variables and then CLI flags on top of
+    // whatever the config file set, so kapy stays scriptable in containers/CI
+    // without mutating the config file; see `Config::resolve`
+    let cli_overrides = match &cli.command {
+        Commands::Clone { from, to, ignore_geotag, .. } => CliOverrides {
+            import_from: from.clone(),
+            import_to: to.clone(),
+            ignore_geotag: if *ignore_geotag { Some(true) } else { None },
+            cred_path: cli.cred.clone(),
+            ..Default::default()
+        },
+        Commands::Login { listen_port, .. } => CliOverrides {
+            listen_port: *listen_port,
+            cred_path: cli.cred.clone(),
+            ..Default::default()
+        },
+        _ => CliOverrides {
+            cred_path: cli.cred.clone(),
+            ..Default::default()
+        },
+    };
+
+    conf.resolve(&cli_overrides);
+
+    let cred_path = conf.cred_path()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| default_cred_path.to_path_buf());
+    let cred_path = cred_path.as_path();
 
     match &cli.command {
-        Commands::Clone { from, to, ignore_geotag, dry_run,after } => {
-            if let Some(from) = from {
-                conf.set_import_from(from.clone());
+        Commands::Clone { jobs, dry_run, after, plan, message_format, gpx_file, location_history, geotag_gpx, .. } => {
+            let ignore_geotag = conf.ignore_geotag();
+
+            if let Some(jobs) = jobs {
+                conf.set_workers(*jobs);
             }
 
-            if let Some(to) = to {
-                conf.set_import_to(to.clone());
+            if *dry_run && *message_format == MessageFormat::Human {
+                conf.print_provenance();
             }
 
-            return clone::do_clone(conf, cred_path, *ignore_geotag, *dry_run, after.clone());
+            return clone::do_clone(conf, cred_path, ignore_geotag, *dry_run, after.clone(), *plan, *message_format,
+                                    gpx_file.clone(), location_history.clone(), geotag_gpx.clone());
         }
         Commands::Clean => {
             return clean::do_clean(cred_path);
         }
-        Commands::Login { listen_port } => {
-            let listen_port = match *listen_port {
+        Commands::Prune { keep_daily, keep_weekly, keep_monthly, keep_yearly, dry_run } => {
+            let policy = prune::RetentionPolicy {
+                keep_daily: *keep_daily,
+                keep_weekly: *keep_weekly,
+                keep_monthly: *keep_monthly,
+                keep_yearly: *keep_yearly,
+            };
+
+            return prune::do_prune(conf, policy, *dry_run);
+        }
+        Commands::Config { action } => {
+            return config_cmd::do_config(conf_path, &conf, action);
+        }
+        Commands::Sync { query, to, full, page_size } => {
+            return sync_cmd::do_sync(cred_path, query, to, *page_size, *full);
+        }
+        Commands::Login { device, token, .. } => {
+            let listen_port = match conf.listen_port() {
                 Some(port) => ListenPort::Port(port),
                 None => ListenPort::DefaultPort,
             };
 
-            return login::do_login(cred_path, listen_port);
+            let auth_flow = if *device { AuthFlow::Device } else { AuthFlow::Browser };
+
+            let token = token.as_deref().map(|value| login::read_token_input(value).unwrap_or_else(|e| {
+                eprintln!("Failed to read token input: {}", e);
+                process::exit(1);
+            }));
+
+            return login::do_login(cred_path, listen_port, auth_flow, token);
         }
         _ => {
             // never reached
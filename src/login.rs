@@ -1,14 +1,45 @@
+use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
 use std::process;
 use console::style;
-use crate::drive::auth::{CredPath, GoogleAuthenticator, ListenPort};
+use crate::drive::auth::{AuthFlow, GoogleAuthenticator, ListenPort, Store};
 
-pub fn do_login(cred_path: &Path, listen_port: ListenPort) {
+// reads the value passed to `--token`: from stdin when it's '-', otherwise
+// from the named file
+pub fn read_token_input(value: &str) -> io::Result<String> {
+    let raw = if value == "-" {
+        let mut buf = String::new();
+        io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        fs::read_to_string(value)?
+    };
+
+    Ok(raw.trim().to_string())
+}
+
+pub fn do_login(cred_path: &Path, listen_port: ListenPort, auth_flow: AuthFlow, token: Option<String>) {
     println!("Login to google drive...");
 
+    let auth = GoogleAuthenticator::new(listen_port, Store::File(cred_path), auth_flow);
+
+    // when a token is supplied out-of-band, seed it directly instead of
+    // running the interactive browser/device flow; `access_token()` below
+    // then exchanges it for a real one without ever touching the loopback
+    // redirect listener
+    if let Some(token) = token {
+        print!("\tImporting supplied credential...");
+        if let Err(e) = auth.import_token(&token) {
+            println!("\t{}", style("[FAILED]").red());
+            eprintln!("Failed to import credential: {}", e);
+            process::exit(1);
+        }
+        println!("\t{}", style("[  OK  ]").green());
+    }
+
     // try to login
     print!("\tTrying to login...");
-    let auth = GoogleAuthenticator::new(listen_port, CredPath::Path(cred_path));
     match auth.access_token() {
         Ok(_) => println!("\t{}", style("[  OK  ]").green()),
         Err(e) => {
@@ -11,7 +11,12 @@ use rayon::prelude::*;
 
 type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
-pub fn encode(blob :Vec<u8>, quality: f32) -> Result<EncodedImage> {
+// `num_threads` bounds rav1e's own thread pool. `encode` is normally called
+// from inside `do_clone`'s outer rayon pool, one call per worker thread, so
+// leaving this at ravif's default (one pool per core) would oversubscribe
+// the machine; callers should pass 1 there and only raise it when they know
+// they're encoding outside of that outer pool.
+pub fn encode(blob: Vec<u8>, quality: f32, num_threads: usize) -> Result<EncodedImage> {
     // transform blob
     let img = load_rgba(&blob, false)
         .map_err(|e| anyhow!("Failed to load rgb: {}", e))?;
@@ -26,7 +31,7 @@ pub fn encode(blob :Vec<u8>, quality: f32) -> Result<EncodedImage> {
         .with_speed(speed)
         .with_alpha_quality(alpha_quality)
         .with_internal_color_space(ColorSpace::YCbCr)
-        .with_num_threads(None)
+        .with_num_threads(Some(num_threads))
         .with_alpha_color_mode(AlphaColorMode::UnassociatedClean);
 
     // encode
@@ -35,7 +40,7 @@ pub fn encode(blob :Vec<u8>, quality: f32) -> Result<EncodedImage> {
 }
 
 #[cfg(not(feature = "cocoa_image"))]
-fn load_rgba(data: &[u8], premultiplied_alpha: bool) -> Result<ImgVec<RGBA8>, BoxError> {
+pub(crate) fn load_rgba(data: &[u8], premultiplied_alpha: bool) -> Result<ImgVec<RGBA8>, BoxError> {
 
     let img = load_image::load_data(data)?.into_imgvec();
     let mut img = match img {
@@ -60,7 +65,7 @@ fn load_rgba(data: &[u8], premultiplied_alpha: bool) -> Result<ImgVec<RGBA8>, Bo
 }
 
 #[cfg(feature = "cocoa_image")]
-fn load_rgba(data: &[u8], premultiplied_alpha: bool) -> Result<ImgVec<RGBA8>, BoxError> {
+pub(crate) fn load_rgba(data: &[u8], premultiplied_alpha: bool) -> Result<ImgVec<RGBA8>, BoxError> {
     if premultiplied_alpha {
         Ok(cocoa_image::decode_image_as_rgba_premultiplied(data)?)
     } else {
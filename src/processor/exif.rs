@@ -1,8 +1,10 @@
 use std::ffi::{c_char, c_int, CStr, CString};
+use std::io::Cursor;
 use std::ops::Deref;
 use std::path::Path;
 
 use anyhow::{anyhow, Result};
+use exif::{In, Tag, Value};
 
 #[repr(C)]
 struct ExifMetadataT {
@@ -18,7 +20,7 @@ extern "C" {
     fn exif_metadata_open(metadata: *mut ExifMetadataT, path: *const c_char) -> c_int;
     fn exif_metadata_open_blob(metadata: *mut ExifMetadataT, blob: *const u8, blob_len: usize) -> c_int;
     fn exif_metadata_save_blob(metadata: *mut ExifMetadataT, blob: *const u8, blob_len: usize, out_blob: *mut *mut u8) -> usize;
-    fn exif_metadata_add_gps_info(metadata: *mut ExifMetadataT, lat: f64, lon: f64, alt: f64) -> c_int;
+    fn exif_metadata_set_tag_string(metadata: *mut ExifMetadataT, tag: *const c_char, value: *const c_char) -> c_int;
     fn exif_get_mime(metadata: *mut ExifMetadataT) -> *mut c_char;
     fn exif_get_tag_string(metadata: *mut ExifMetadataT, tag: *const c_char) -> *mut c_char;
     fn exif_metadata_destroy(metadata: *const *mut ExifMetadataT);
@@ -107,14 +109,18 @@ impl Metadata {
         }
     }
 
-    pub fn add_gps_info(&self, gps_info: GpsInfo) -> Result<()> {
+    pub fn set_tag<T, U>(&self, tag: T, value: U) -> Result<()>
+        where T: AsRef<str>, U: AsRef<str> {
+        let tag = CString::new(tag.as_ref()).unwrap();
+        let value = CString::new(value.as_ref()).unwrap();
+
         unsafe {
-            let rc = exif_metadata_add_gps_info(self.raw, gps_info.lat, gps_info.lon, gps_info.lon);
+            let rc = exif_metadata_set_tag_string(self.raw, tag.as_ptr(), value.as_ptr());
 
-            if rc != 0 {
-                Err(anyhow!("Failed to add gps info"))
-            }  else {
+            if rc == 0 {
                 Ok(())
+            } else {
+                Err(anyhow!("Failed to set tag '{}'", tag.to_string_lossy()))
             }
         }
     }
@@ -135,8 +141,173 @@ impl Metadata {
     }
 }
 
+#[derive(Clone)]
 pub struct GpsInfo {
     pub lat: f64,
     pub lon: f64,
     pub alt: f64,
 }
+
+// GPS-only EXIF read/write, kept separate from the `Metadata` FFI above: the
+// C++/exiv2 wrapper still owns general tag access (`get_tag`/`set_tag`, used
+// for `DateTimeOriginal`, pixel dimensions, orientation, ...), but GPS is
+// read and written here in pure Rust so adding/checking a fix never has to
+// round-trip through the FFI boundary. Only JPEG is supported:
+// `write_gps_info` returns an error for any other blob, including the
+// RAW-decoded and AVIF blobs the clone pipeline may hand it.
+
+// whether `blob` already carries a GPS fix, so the clone pipeline can skip
+// photos that are already geotagged
+pub fn is_geotagged(blob: &[u8]) -> bool {
+    read_gps_fix(blob).is_some()
+}
+
+fn read_gps_fix(blob: &[u8]) -> Option<(f64, f64, f64)> {
+    let mut cursor = Cursor::new(blob);
+    let exif = exif::Reader::new().read_from_container(&mut cursor).ok()?;
+
+    let lat = read_dms_tag(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?;
+    let lon = read_dms_tag(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?;
+    let alt = read_altitude_tag(&exif).unwrap_or(0.0);
+
+    Some((lat, lon, alt))
+}
+
+fn read_ascii_tag(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(ref v) => {
+            let s = String::from_utf8_lossy(v.first()?).into_owned();
+            Some(s.trim_end_matches('\0').to_string())
+        }
+        _ => None,
+    }
+}
+
+fn read_dms_tag(exif: &exif::Exif, value_tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let value = exif.get_field(value_tag, In::PRIMARY)?;
+    let rationals = match value.value {
+        Value::Rational(ref v) => v,
+        _ => return None,
+    };
+    if rationals.len() < 3 {
+        return None;
+    }
+
+    let degrees = rationals[0].to_f64() + rationals[1].to_f64() / 60.0 + rationals[2].to_f64() / 3600.0;
+    let negative = read_ascii_tag(exif, ref_tag).as_deref() == Some(negative_ref);
+
+    Some(if negative { -degrees } else { degrees })
+}
+
+fn read_altitude_tag(exif: &exif::Exif) -> Option<f64> {
+    let value = exif.get_field(Tag::GPSAltitude, In::PRIMARY)?;
+    let rational = match value.value {
+        Value::Rational(ref v) => v.first()?,
+        _ => return None,
+    };
+
+    let negative = matches!(exif.get_field(Tag::GPSAltitudeRef, In::PRIMARY)?.value, Value::Byte(ref v) if v.first() == Some(&1));
+
+    Some(if negative { -rational.to_f64() } else { rational.to_f64() })
+}
+
+// writes a fresh GPS IFD into `blob` as a new APP1 segment right after SOI,
+// replacing any APP1 segment(s) that directly follow SOI; an APP1 that comes
+// after another segment (e.g. a JFIF APP0) is kept. Hand-rolled because there's no mature
+// pure-Rust EXIF *writer*; the IFD layout below is a minimal, fixed-offset
+// TIFF carrying exactly one GPS sub-IFD and nothing else.
+pub fn write_gps_info(blob: &[u8], gps_info: &GpsInfo) -> Result<Vec<u8>> {
+    if blob.len() < 2 || blob[0] != 0xFF || blob[1] != 0xD8 {
+        return Err(anyhow!("not a JPEG blob"));
+    }
+
+    let tiff = build_gps_tiff(gps_info.lat, gps_info.lon, gps_info.alt);
+
+    let mut payload = Vec::with_capacity(6 + tiff.len());
+    payload.extend_from_slice(b"Exif\0\0");
+    payload.extend_from_slice(&tiff);
+
+    let segment_len = payload.len() + 2;
+    if segment_len > 0xFFFF {
+        return Err(anyhow!("GPS APP1 segment too large"));
+    }
+
+    let mut out = Vec::with_capacity(blob.len() + segment_len + 2);
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&(segment_len as u16).to_be_bytes());
+    out.extend_from_slice(&payload);
+
+    // skip over any pre-existing APP1 segment(s) directly after SOI
+    let mut pos = 2;
+    while pos + 4 <= blob.len() && blob[pos] == 0xFF && blob[pos + 1] == 0xE1 {
+        let len = u16::from_be_bytes([blob[pos + 2], blob[pos + 3]]) as usize;
+        pos += 2 + len;
+    }
+    out.extend_from_slice(&blob[pos..]);
+
+    Ok(out)
+}
+
+fn build_gps_tiff(lat: f64, lon: f64, alt: f64) -> Vec<u8> {
+    const GPS_IFD_OFFSET: u32 = 26;
+    const LAT_DATA_OFFSET: u32 = 116;
+    const LON_DATA_OFFSET: u32 = 140;
+    const ALT_DATA_OFFSET: u32 = 164;
+
+    let mut tiff = Vec::with_capacity(172);
+
+    // TIFF header: little-endian, magic 42, IFD0 at offset 8
+    tiff.extend_from_slice(b"II");
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+
+    // IFD0: a single entry pointing at the GPS sub-IFD
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    write_ifd_entry(&mut tiff, 0x8825, 4, 1, &GPS_IFD_OFFSET.to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes()); // no IFD1
+
+    // GPS sub-IFD
+    tiff.extend_from_slice(&7u16.to_le_bytes());
+    write_ifd_entry(&mut tiff, 0x0000, 1, 4, &[2, 3, 0, 0]); // GPSVersionID
+    write_ifd_entry(&mut tiff, 0x0001, 2, 2, &[if lat >= 0.0 { b'N' } else { b'S' }, 0]);
+    write_ifd_entry(&mut tiff, 0x0002, 5, 3, &LAT_DATA_OFFSET.to_le_bytes());
+    write_ifd_entry(&mut tiff, 0x0003, 2, 2, &[if lon >= 0.0 { b'E' } else { b'W' }, 0]);
+    write_ifd_entry(&mut tiff, 0x0004, 5, 3, &LON_DATA_OFFSET.to_le_bytes());
+    write_ifd_entry(&mut tiff, 0x0005, 1, 1, &[if alt < 0.0 { 1 } else { 0 }, 0, 0, 0]);
+    write_ifd_entry(&mut tiff, 0x0006, 5, 1, &ALT_DATA_OFFSET.to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes()); // no next IFD
+
+    write_dms_rational(&mut tiff, lat);
+    write_dms_rational(&mut tiff, lon);
+    write_rational(&mut tiff, alt.abs(), 1_000);
+
+    tiff
+}
+
+fn write_ifd_entry(tiff: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: &[u8]) {
+    tiff.extend_from_slice(&tag.to_le_bytes());
+    tiff.extend_from_slice(&field_type.to_le_bytes());
+    tiff.extend_from_slice(&count.to_le_bytes());
+
+    let mut padded = [0u8; 4];
+    padded[..value.len()].copy_from_slice(value);
+    tiff.extend_from_slice(&padded);
+}
+
+fn write_dms_rational(tiff: &mut Vec<u8>, value: f64) {
+    let value = value.abs();
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.0).trunc();
+    let seconds = ((value - degrees) * 60.0 - minutes) * 60.0;
+
+    write_rational(tiff, degrees, 1);
+    write_rational(tiff, minutes, 1);
+    write_rational(tiff, seconds, 1_000_000);
+}
+
+fn write_rational(tiff: &mut Vec<u8>, value: f64, denom: u32) {
+    let numer = (value * denom as f64).round() as u32;
+    tiff.extend_from_slice(&numer.to_le_bytes());
+    tiff.extend_from_slice(&denom.to_le_bytes());
+}
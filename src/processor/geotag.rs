@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+use chrono::{FixedOffset, NaiveDateTime, TimeZone};
+
+use crate::processor::exif::{GpsInfo, Metadata};
+
+const EXIF_DATETIME_ORIGINAL: &str = "Exif.Photo.DateTimeOriginal";
+
+// how a photo's `DateTimeOriginal` is matched against a GPX track: the
+// timezone the camera clock was set to (EXIF carries no timezone of its
+// own), an extra shift to correct for camera-clock drift against the GPS
+// receiver, and how far past either end of the track a photo may still
+// snap to the nearest point
+pub struct GeotagConfig {
+    pub camera_tz_offset: FixedOffset,
+    pub clock_shift_secs: i64,
+    pub snap_tolerance_secs: i64,
+}
+
+impl Default for GeotagConfig {
+    fn default() -> Self {
+        GeotagConfig {
+            camera_tz_offset: FixedOffset::east_opt(0).unwrap(),
+            clock_shift_secs: 0,
+            snap_tolerance_secs: 60,
+        }
+    }
+}
+
+// a single parsed GPX track point, sorted and searched by `timestamp`
+// (seconds since the Unix epoch, UTC)
+#[derive(Debug, Clone, Copy)]
+struct TrackPoint {
+    timestamp: i64,
+    lat: f64,
+    lon: f64,
+    ele: f64,
+}
+
+// parses a GPX blob into a time-ordered list of `(timestamp, lat, lon,
+// ele)` track points, reads the photo's `DateTimeOriginal`, and - if a
+// position can be resolved - returns it as a `GpsInfo` for the caller to
+// write back with `exif::write_gps_info`.
+pub fn geotag_photo(gpx_blob: &[u8], metadata: &Metadata, config: &GeotagConfig) -> Result<Option<GpsInfo>> {
+    let points = parse_track(gpx_blob)?;
+    if points.is_empty() {
+        return Ok(None);
+    }
+
+    let datetime_str = metadata.get_tag(EXIF_DATETIME_ORIGINAL)?;
+    let naive = NaiveDateTime::parse_from_str(&datetime_str, "%Y:%m:%d %H:%M:%S")?;
+
+    let camera_time = config.camera_tz_offset.from_local_datetime(&naive).single()
+        .ok_or_else(|| anyhow!("ambiguous or invalid local photo time '{}'", datetime_str))?;
+    let t = camera_time.timestamp() + config.clock_shift_secs;
+
+    let fix = match locate(&points, t, config.snap_tolerance_secs) {
+        Some(fix) => fix,
+        None => return Ok(None),
+    };
+
+    Ok(Some(GpsInfo { lat: fix.0, lon: fix.1, alt: fix.2 }))
+}
+
+fn parse_track(gpx_blob: &[u8]) -> Result<Vec<TrackPoint>> {
+    let gpx = gpx::read(gpx_blob)?;
+
+    let mut points = Vec::new();
+
+    for track in gpx.tracks.iter() {
+        for segment in track.segments.iter() {
+            for waypoint in segment.points.iter() {
+                let t = match &waypoint.time {
+                    Some(t) => t,
+                    None => continue,
+                };
+
+                let dt = chrono::DateTime::parse_from_rfc3339(&t.format().unwrap()).unwrap();
+
+                points.push(TrackPoint {
+                    timestamp: dt.timestamp(),
+                    lat: waypoint.point().y(),
+                    lon: waypoint.point().x(),
+                    ele: waypoint.elevation.unwrap_or(0.0),
+                });
+            }
+        }
+    }
+
+    points.sort_by_key(|p| p.timestamp);
+
+    Ok(points)
+}
+
+// binary-searches `points` for `t`; interpolates between the bracketing
+// points by the time fraction `(t - t0)/(t1 - t0)` when `t` falls inside
+// the track, or snaps to the nearest endpoint when it falls just outside
+// (within `snap_tolerance_secs`), otherwise gives up
+fn locate(points: &[TrackPoint], t: i64, snap_tolerance_secs: i64) -> Option<(f64, f64, f64)> {
+    match points.binary_search_by_key(&t, |p| p.timestamp) {
+        Ok(idx) => {
+            let p = &points[idx];
+            Some((p.lat, p.lon, p.ele))
+        }
+        Err(0) => {
+            let first = &points[0];
+            if first.timestamp - t <= snap_tolerance_secs {
+                Some((first.lat, first.lon, first.ele))
+            } else {
+                None
+            }
+        }
+        Err(idx) if idx == points.len() => {
+            let last = &points[points.len() - 1];
+            if t - last.timestamp <= snap_tolerance_secs {
+                Some((last.lat, last.lon, last.ele))
+            } else {
+                None
+            }
+        }
+        Err(idx) => {
+            let p0 = &points[idx - 1];
+            let p1 = &points[idx];
+
+            let span = p1.timestamp - p0.timestamp;
+            if span == 0 {
+                return Some((p0.lat, p0.lon, p0.ele));
+            }
+
+            let f = (t - p0.timestamp) as f64 / span as f64;
+
+            Some((
+                p0.lat + f * (p1.lat - p0.lat),
+                p0.lon + f * (p1.lon - p0.lon),
+                p0.ele + f * (p1.ele - p0.ele),
+            ))
+        }
+    }
+}
@@ -1,30 +1,227 @@
 use std::collections::BTreeMap;
 use std::cmp::Ordering;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use anyhow::{Result, anyhow};
-use chrono::DateTime;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use flate2::read::GzDecoder;
+use geo_types::Point;
 use magick_rust::{MagickWand, bindings};
 use gpx::{Gpx, Waypoint};
 use regex::internal::Input;
+use serde::Deserialize;
 use crate::drive::GoogleDrive;
 
-pub struct GeoTags {
-    drive: GoogleDrive,
-    match_within: Duration,
-    cached: GeoCache,
+// matches a photo's capture time against some source of GPS waypoints; lets
+// the clone pipeline swap in a no-op when `--ignore-geotag` is passed, or a
+// real track-backed search otherwise, behind the same `Arc<Box<dyn _>>` it
+// already threads through `clone_image`. `Send + Sync` since that `Arc` is
+// shared across the rayon pool `do_clone` runs the clone loop on.
+pub trait GpsSearch: Send + Sync {
+    fn search(&self, t: &DateTime<FixedOffset>) -> Option<Waypoint>;
 }
 
-impl GeoTags {
-    pub fn new(drive: GoogleDrive, match_within: Duration) -> GeoTags {
-        GeoTags {
-            drive,
-            match_within,
-            cached: GeoCache::new(match_within),
+pub struct NoopGpsSearch;
+
+impl GpsSearch for NoopGpsSearch {
+    fn search(&self, _t: &DateTime<FixedOffset>) -> Option<Waypoint> {
+        None
+    }
+}
+
+// collects the `(photo_name, Waypoint)` pairs a clone actually matched so
+// they can be written back out as a single shareable/round-trippable `.gpx`
+// of "where each photo was taken", alongside the usual JSON manifest
+pub struct GeoExport {
+    entries: Vec<(String, Waypoint)>,
+}
+
+impl GeoExport {
+    pub fn new() -> GeoExport {
+        GeoExport { entries: Vec::new() }
+    }
+
+    pub fn push(&mut self, photo_name: String, waypoint: Waypoint) {
+        self.entries.push((photo_name, waypoint));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let mut gpx = Gpx::default();
+        gpx.version = gpx::GpxVersion::Gpx11;
+
+        for (photo_name, waypoint) in self.entries.iter() {
+            let mut wpt = waypoint.clone();
+            wpt.name = Some(photo_name.clone());
+            gpx.waypoints.push(wpt);
+        }
+
+        let file = fs::File::create(path)?;
+        gpx::write(&gpx, file)?;
+
+        Ok(())
+    }
+}
+
+// restricts which photos get copied to those whose matched GPS coordinates
+// fall inside a user-specified area; the two variants mirror the
+// `geo_bounding_box`/`geo_radius` filters common to geo-search engines
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum GeoFilter {
+    #[serde(rename = "geo_bounding_box")]
+    BoundingBox { top_left: (f64, f64), bottom_right: (f64, f64) },
+    #[serde(rename = "geo_radius")]
+    Radius { lat: f64, lon: f64, meters: f64 },
+}
+
+impl GeoFilter {
+    pub fn matches(&self, waypoint: &Waypoint) -> bool {
+        let lat = waypoint.point().y();
+        let lon = waypoint.point().x();
+
+        match self {
+            GeoFilter::BoundingBox { top_left, bottom_right } => {
+                geo_bounding_box(lat, lon, *top_left, *bottom_right)
+            }
+            GeoFilter::Radius { lat: center_lat, lon: center_lon, meters } => {
+                geo_radius(lat, lon, *center_lat, *center_lon, *meters)
+            }
+        }
+    }
+}
+
+// straightforward lat/lon range check; `bottom_right`'s longitude is allowed
+// to be less than `top_left`'s so a box can straddle the antimeridian, but a
+// box whose top latitude sits below its bottom is always rejected
+fn geo_bounding_box(lat: f64, lon: f64, top_left: (f64, f64), bottom_right: (f64, f64)) -> bool {
+    let (lat_top, lon_left) = top_left;
+    let (lat_bottom, lon_right) = bottom_right;
+
+    if lat_top < lat_bottom {
+        return false;
+    }
+
+    if lat < lat_bottom || lat > lat_top {
+        return false;
+    }
+
+    if lon_left <= lon_right {
+        lon >= lon_left && lon <= lon_right
+    } else {
+        lon >= lon_left || lon <= lon_right
+    }
+}
+
+// great-circle distance via the haversine formula, compared against a
+// radius threshold in meters
+fn geo_radius(lat: f64, lon: f64, center_lat: f64, center_lon: f64, meters: f64) -> bool {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+    let lat1 = lat.to_radians();
+    let lat2 = center_lat.to_radians();
+    let dlat = (center_lat - lat).to_radians();
+    let dlon = (center_lon - lon).to_radians();
+
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().asin();
+
+    EARTH_RADIUS_M * c <= meters
+}
+
+// a `GeoCache` populated from the GPX track files found on the user's Google
+// Drive within a time window, from a local GPX file, or from a Takeout
+// location history; this is what `do_clone` searches against unless
+// geotagging is disabled or `--geotag-gpx` is used
+pub struct GpxStorage {
+    cache: GeoCache,
+}
+
+impl GpsSearch for GpxStorage {
+    fn search(&self, t: &DateTime<FixedOffset>) -> Option<Waypoint> {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(t.timestamp().max(0) as u64);
+
+        self.cache.search(t)
+    }
+}
+
+impl GpxStorage {
+    // loads a single GPX track straight off local disk; transparently
+    // decompresses it first if it's gzipped, same as `from_google_drive`
+    pub fn from_file(path: &Path, match_within: Duration) -> Result<GpxStorage> {
+        let bytes = fs::read(path)?;
+        let gpx = read_gpx(&bytes)?;
+
+        let mut cache = GeoCache::new(match_within);
+        cache.pour_into(gpx)?;
+
+        Ok(GpxStorage { cache })
+    }
+
+    // loads a Google Takeout `Records.json` location history export instead
+    // of a GPX track, for users who carry no GPS logger of their own
+    pub fn from_location_history(path: &Path, match_within: Duration) -> Result<GpxStorage> {
+        let bytes = fs::read(path)?;
+        let history: LocationHistory = serde_json::from_slice(&bytes)?;
+
+        let mut cache = GeoCache::new(match_within);
+        cache.pour_location_history(history)?;
+
+        Ok(GpxStorage { cache })
+    }
+
+    pub fn from_google_drive<F>(drive: &GoogleDrive, start: SystemTime, end: SystemTime,
+                                max_files: usize, match_within: Duration,
+                                mut when_downloading: F) -> Result<GpxStorage>
+        where
+            F: FnMut(&str)
+    {
+        let start_rfc3339 = DateTime::<chrono::Utc>::from(start).to_rfc3339();
+        let end_rfc3339 = DateTime::<chrono::Utc>::from(end).to_rfc3339();
+
+        let q = format!("name contains '.gpx' and modifiedTime > '{}' and modifiedTime < '{}' and trashed = false",
+                        start_rfc3339, end_rfc3339);
+
+        let mut cache = GeoCache::new(match_within);
+        let mut next_page_token: Option<String> = None;
+
+        'paging: loop {
+            let response = drive.list(&q, max_files, next_page_token.as_deref())?;
+
+            for file in response.files.iter() {
+                when_downloading(&file.name);
+
+                let blob = drive.download_blob(&file.id)?;
+                let gpx = read_gpx(blob.as_ref())?;
+
+                cache.pour_into(gpx)?;
+            }
+
+            match response.next_page_token {
+                Some(token) => {
+                    next_page_token = Some(token);
+                }
+                None => break 'paging,
+            }
         }
+
+        Ok(GpxStorage { cache })
     }
+}
 
-    pub fn search(&self, t: SystemTime) -> Result<Waypoint> {
-        todo!()
+// transparently decompresses gzipped GPX (`.gpx.gz`, a common way track logs
+// get shared) by sniffing the gzip magic (`0x1f 0x8b`) before handing the
+// stream to `gpx::read`; anything else is assumed to already be plain XML
+fn read_gpx(bytes: &[u8]) -> Result<Gpx> {
+    if bytes.len() >= 2 && bytes[0] == 0x1f && bytes[1] == 0x8b {
+        let decoder = GzDecoder::new(bytes);
+        Ok(gpx::read(BufReader::new(decoder))?)
+    } else {
+        Ok(gpx::read(BufReader::new(bytes))?)
     }
 }
 
@@ -72,8 +269,13 @@ impl TimestampKey for Duration {
     }
 }
 
+// beyond this implied speed (m/s) between two bracketing waypoints, treat the
+// gap as a GPS glitch rather than something worth interpolating across
+const MAX_PLAUSIBLE_SPEED_MPS: f64 = 60.0; // ~216 km/h
+
 trait SearchWaypoint {
     fn closest(&self, t: i64) -> Option<Waypoint>;
+    fn interpolate(&self, t: i64, match_within: &Duration) -> Option<Waypoint>;
 }
 
 impl SearchWaypoint for Vec<Waypoint> {
@@ -92,6 +294,80 @@ impl SearchWaypoint for Vec<Waypoint> {
             None => None
         }
     }
+
+    // interpolate a synthetic waypoint between the bracketing waypoints
+    // around `t`, falling back to `closest` when there is nothing to
+    // bracket with, the gap is too wide, or the implied jump is implausible
+    fn interpolate(&self, t: i64, match_within: &Duration) -> Option<Waypoint> {
+        let mut prev: Option<&Waypoint> = None;
+        let mut next: Option<&Waypoint> = None;
+
+        for waypoint in self.iter() {
+            let wt = match waypoint.unix_at() {
+                Some(wt) => wt,
+                None => continue,
+            };
+
+            if wt <= t && prev.map_or(true, |p| wt > p.unix_at().unwrap()) {
+                prev = Some(waypoint);
+            }
+            if wt >= t && next.map_or(true, |n| wt < n.unix_at().unwrap()) {
+                next = Some(waypoint);
+            }
+        }
+
+        let (prev, next) = match (prev, next) {
+            (Some(prev), Some(next)) => (prev, next),
+            _ => return self.closest(t),
+        };
+
+        let t_prev = prev.unix_at().unwrap();
+        let t_next = next.unix_at().unwrap();
+
+        if t_prev == t_next {
+            return Some(prev.clone());
+        }
+
+        let gap = t_next - t_prev;
+        if gap > match_within.as_secs() as i64 {
+            return self.closest(t);
+        }
+
+        if equirect_distance_m(prev, next) / gap as f64 > MAX_PLAUSIBLE_SPEED_MPS {
+            return self.closest(t);
+        }
+
+        let f = (t - t_prev) as f64 / gap as f64;
+
+        let lat = prev.point().y() + f * (next.point().y() - prev.point().y());
+        let lon = prev.point().x() + f * (next.point().x() - prev.point().x());
+
+        let mut waypoint = Waypoint::new(Point::new(lon, lat));
+        waypoint.elevation = match (prev.elevation, next.elevation) {
+            (Some(e_prev), Some(e_next)) => Some(e_prev + f * (e_next - e_prev)),
+            (Some(e_prev), None) => Some(e_prev),
+            (None, Some(e_next)) => Some(e_next),
+            (None, None) => None,
+        };
+        waypoint.time = prev.time.clone();
+
+        Some(waypoint)
+    }
+}
+
+// equirectangular approximation of the distance (in meters) between two
+// waypoints; cheap and accurate enough at the scale of a GPX bracketing gap
+fn equirect_distance_m(a: &Waypoint, b: &Waypoint) -> f64 {
+    let lat_a = a.point().y();
+    let lon_a = a.point().x();
+    let lat_b = b.point().y();
+    let lon_b = b.point().x();
+
+    let mean_lat_rad = ((lat_a + lat_b) / 2.0).to_radians();
+    let x = (lon_b - lon_a) * mean_lat_rad.cos() * 111_320.0;
+    let y = (lat_b - lat_a) * 110_540.0;
+
+    (x * x + y * y).sqrt()
 }
 
 impl GeoCache {
@@ -122,7 +398,7 @@ impl GeoCache {
             }
         }
 
-        target.closest(t)
+        target.interpolate(t, &self.match_within)
     }
 
     fn pour_into(&mut self, data: Gpx) -> Result<i32> {
@@ -131,24 +407,8 @@ impl GeoCache {
         for track in data.tracks.iter() {
             for segment in track.segments.iter() {
                 for waypoint in segment.points.iter() {
-                    match waypoint.unix_at() {
-                        Some(t) => {
-                            let key = self.match_within.make_key(t);
-                            match self.cache.get_mut(&key) {
-                                Some(l) => {
-                                    // insert into existed list
-                                    l.push(waypoint.clone());
-                                    counts += 1;
-                                }
-                                None => {
-                                    // make new list
-                                    let l = vec![waypoint.clone()];
-                                    self.cache.insert(key, l);
-                                    counts += 1;
-                                }
-                            }
-                        }
-                        None => continue,
+                    if self.insert(waypoint.clone()) {
+                        counts += 1;
                     }
                 }
             }
@@ -156,28 +416,88 @@ impl GeoCache {
 
         Ok(counts)
     }
-}
 
-// native implementation to add gps info
-extern "C" {
-    fn native_add_gps_info(blob: *mut u8, blob_len: usize, out_blob: *mut *mut u8, lat: f64, lon: f64, alt: f64) -> usize;
+    // ingest a Google Takeout `Records.json` location history export, which
+    // carries no GPX track of its own; each record is turned into a
+    // `Waypoint` and bucketed through the same keying logic as `pour_into` so
+    // `GeoCache`/`GpxStorage` can geotag photos from a Takeout export alone
+    fn pour_location_history(&mut self, history: LocationHistory) -> Result<i32> {
+        let mut counts = 0;
+
+        for record in history.locations.iter() {
+            let lat = record.latitude_e7 as f64 / 1e7;
+            let lon = record.longitude_e7 as f64 / 1e7;
+
+            let time = match record.time() {
+                Some(time) => time,
+                None => continue,
+            };
+
+            let mut waypoint = Waypoint::new(Point::new(lon, lat));
+            waypoint.time = Some(time);
+
+            if self.insert(waypoint) {
+                counts += 1;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    // bucket a single waypoint by its rounded-down timestamp key; returns
+    // `false` for waypoints without a usable time, which can't be searched
+    // for anyway
+    fn insert(&mut self, waypoint: Waypoint) -> bool {
+        let t = match waypoint.unix_at() {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let key = self.match_within.make_key(t);
+        self.cache.entry(key).or_insert_with(Vec::new).push(waypoint);
+
+        true
+    }
 }
 
-// safe implementation to add gps info
-fn add_gps_info(mut blob: Vec<u8>, lat: f64, lon: f64, alt: f64) -> Result<Vec<u8>> {
-    let mut new_len = 0;
+// top-level shape of a Google Takeout `Records.json` export
+#[derive(Deserialize, Debug)]
+struct LocationHistory {
+    locations: Vec<LocationRecord>,
+}
 
-    unsafe {
-        let blob_len = blob.len();
-        let mut out_blob: *mut u8 = std::ptr::null_mut();
+#[derive(Deserialize, Debug)]
+struct LocationRecord {
+    #[serde(rename = "latitudeE7")]
+    latitude_e7: i64,
+    #[serde(rename = "longitudeE7")]
+    longitude_e7: i64,
+    timestamp: Option<String>,
+    #[serde(rename = "timestampMs")]
+    timestamp_ms: Option<String>,
+}
 
-        new_len = native_add_gps_info(blob.as_mut_ptr(), blob_len, &mut out_blob, lat, lon, alt);
-        if new_len > 0 {
-            Ok(Vec::from_raw_parts(out_blob, new_len, new_len))
+impl LocationRecord {
+    // milliseconds since the epoch, from either the RFC3339 `timestamp` or
+    // the legacy `timestampMs` field
+    fn unix_millis(&self) -> Option<i64> {
+        if let Some(ref ts) = self.timestamp {
+            Some(DateTime::parse_from_rfc3339(ts).ok()?.timestamp_millis())
+        } else if let Some(ref ts_ms) = self.timestamp_ms {
+            ts_ms.parse().ok()
         } else {
-            Err(anyhow!("Failed to add gps info"))
+            None
         }
     }
+
+    fn time(&self) -> Option<gpx::Time> {
+        let millis = self.unix_millis()?;
+
+        let offset_dt = time::OffsetDateTime::from_unix_timestamp(millis.div_euclid(1000)).ok()?
+            .replace_nanosecond((millis.rem_euclid(1000) * 1_000_000) as u32).ok()?;
+
+        Some(gpx::Time::from(offset_dt))
+    }
 }
 
 #[cfg(test)]
@@ -339,24 +659,3 @@ mod tests {
 "#;
 }
 
-/*
-$ exiv2 -pa IMAGE.JPG | grep -i gps
-Exif.Image.GPSTag                            Long        1  6406
-Exif.GPSInfo.GPSVersionID                    Byte        4  2.3.0.0
-Exif.GPSInfo.GPSLatitudeRef                  Ascii       2  North
-Exif.GPSInfo.GPSLatitude                     Rational    3  37deg 17' 13"
-Exif.GPSInfo.GPSLongitudeRef                 Ascii       2  East
-Exif.GPSInfo.GPSLongitude                    Rational    3  126deg 32' 12"
-Exif.GPSInfo.GPSAltitudeRef                  Byte        1  Above sea level
-Exif.GPSInfo.GPSAltitude                     Rational    1  16.3 m
-
-$ identify -verbose IMAGE.JPG | grep -i gps
-    exif:GPSInfo: 6406
-    exif:GPSVersionID: ....
-    exif:GPSLatitudeRef: N
-    exif:GPSLatitude: 37/1, 17/1, 8367/625
-    exif:GPSLongitudeRef: E
-    exif:GPSLongitude: 126/1, 32/1, 15411/1250
-    exif:GPSAltitudeRef: .
-    exif:GPSAltitude: 21229/1303
- */
\ No newline at end of file
@@ -1,10 +1,21 @@
 use std::ffi::{c_void, CString};
 use std::slice;
 use anyhow::{anyhow, Result};
-use libheif_rs::{Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, Image, LibHeif, RgbChroma};
+use libheif_rs::{Channel, ColorProfileNclx, ColorProfileType, ColorSpace, CompressionFormat, EncoderQuality,
+                 HeifContext, Image, LibHeif, MatrixCoefficients, RgbChroma, TransferCharacteristics,
+                 ColorPrimaries};
 use magick_rust::{bindings, MagickWand};
 
+// encodes the wand's current pixels straight through libheif as HEVC/HEIC;
+// `quality` is a 0..=100 percentage, matching the scale the rest of the
+// pipeline already uses for JPEG/AVIF/WebP output
 pub fn encode(wand: &mut MagickWand, quality: u8) -> Result<Vec<u8>> {
+    let (image, exif_profile, xmp_profile) = build_plane(wand)?;
+
+    encode_plane(&image, &exif_profile, &xmp_profile, quality)
+}
+
+fn build_plane(wand: &mut MagickWand) -> Result<(Image, Option<Vec<u8>>, Option<Vec<u8>>)> {
     let width = wand.get_image_width();
     let height = wand.get_image_height();
 
@@ -18,6 +29,19 @@ pub fn encode(wand: &mut MagickWand, quality: u8) -> Result<Vec<u8>> {
         None => return Err(anyhow!("Failed to export image pixels"))
     };
 
+    // guard against dimension/stride overflow before indexing into the
+    // exported blob or the allocated plane: a malformed or very large image
+    // should produce a descriptive error, not a panic or an out-of-bounds copy
+    let expected_len = (width as usize).checked_mul(height as usize)
+        .and_then(|px| px.checked_mul(3))
+        .ok_or_else(|| anyhow!("Image dimensions ({}, {}) overflow while computing buffer size", width, height))?;
+
+    let blob_slice = blob.as_slice();
+    if blob_slice.len() != expected_len {
+        return Err(anyhow!("Exported pixel buffer has unexpected length: expected {}, got {}",
+                expected_len, blob_slice.len()));
+    }
+
     // make image to encode
     let width = width as u32;
     let height = height as u32;
@@ -31,30 +55,55 @@ pub fn encode(wand: &mut MagickWand, quality: u8) -> Result<Vec<u8>> {
     let stride = interleaved_plane.stride;
 
     // fill image pixels
-    let blob_slice = blob.as_slice();
-
     let width = width as usize;
-    for y in 0..height as usize{
-        let x0_for_blob = y * width * 3;
-        let x0_for_data = y * stride;
+    for y in 0..height as usize {
+        let x0_for_blob = y.checked_mul(width)
+            .and_then(|v| v.checked_mul(3))
+            .ok_or_else(|| anyhow!("Row offset overflow at y={} while reading source pixels", y))?;
+        let x0_for_data = y.checked_mul(stride)
+            .ok_or_else(|| anyhow!("Row offset overflow at y={} while writing plane", y))?;
+        let row_len = width.checked_mul(3)
+            .ok_or_else(|| anyhow!("Row length overflow for width={}", width))?;
+
+        data[x0_for_data..x0_for_data + row_len].clone_from_slice(&blob_slice[x0_for_blob..x0_for_blob + row_len])
+    }
 
-        data[x0_for_data..x0_for_data+width*3].clone_from_slice(&blob_slice[x0_for_blob..x0_for_blob +width*3])
+    // carry color information through the RGB round-trip: prefer the
+    // embedded ICC profile when present, otherwise fall back to a plain
+    // sRGB NCLX profile so color-managed viewers don't guess
+    match get_image_profile(wand, "icc") {
+        Some(icc) => {
+            image.set_color_profile_raw(ColorProfileType::Prof, &icc)?;
+        }
+        None => {
+            let nclx = ColorProfileNclx::new(
+                ColorPrimaries::Srgb,
+                TransferCharacteristics::Srgb,
+                MatrixCoefficients::Srgb,
+                true,
+            )?;
+            image.set_color_profile_nclx(&nclx)?;
+        }
     }
 
-    // encode image
+    Ok((image, exif_profile, xmp_profile))
+}
+
+fn encode_plane(image: &Image, exif_profile: &Option<Vec<u8>>, xmp_profile: &Option<Vec<u8>>,
+                quality: u8) -> Result<Vec<u8>> {
     let lib_heif = LibHeif::new();
     let mut context = HeifContext::new()?;
     let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Hevc)?;
     encoder.set_quality(EncoderQuality::Lossy(interpolate_quality(quality)))?;
-    let handle = context.encode_image(&image, &mut encoder, None)?;
+    let handle = context.encode_image(image, &mut encoder, None)?;
 
     // add metadata
     if let Some(exif) = exif_profile {
-        context.add_exif_metadata(&handle, &exif)?;
+        context.add_exif_metadata(&handle, exif)?;
     }
 
     if let Some(xmp) = xmp_profile {
-        context.add_xmp_metadata(&handle, &xmp)?;
+        context.add_xmp_metadata(&handle, xmp)?;
     }
 
     context.write_to_bytes()
@@ -135,3 +184,129 @@ fn get_image_profile<T: AsRef<str>>(wand: &mut MagickWand, name: T) -> Option<Ve
 
     value
 }
+
+const BLURHASH_ALPHABET: &[u8; 83] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// a short ASCII placeholder string for progressive loading, computed from the
+// same RGB blob `encode()` exports; `components_x`/`components_y` control the
+// amount of detail captured (1..=9 each, following the reference blurhash
+// algorithm)
+pub fn blur_hash(wand: &mut MagickWand, components_x: u8, components_y: u8) -> Result<String> {
+    if !(1..=9).contains(&components_x) || !(1..=9).contains(&components_y) {
+        return Err(anyhow!("Component counts must be within 1..=9, got ({}, {})", components_x, components_y));
+    }
+
+    let width = wand.get_image_width();
+    let height = wand.get_image_height();
+
+    let blob = match wand.export_image_pixels(0, 0, width, height, "RGB") {
+        Some(rgb) => rgb,
+        None => return Err(anyhow!("Failed to export image pixels"))
+    };
+    let blob = blob.as_slice();
+
+    let mut factors = vec![[0_f64; 3]; components_x as usize * components_y as usize];
+
+    for j in 0..components_y as usize {
+        for i in 0..components_x as usize {
+            let normalisation = if i == 0 && j == 0 { 1. } else { 2. };
+            let mut r = 0_f64;
+            let mut g = 0_f64;
+            let mut b = 0_f64;
+
+            for y in 0..height as usize {
+                let basis_y = (std::f64::consts::PI * j as f64 * y as f64 / height as f64).cos();
+
+                for x in 0..width as usize {
+                    let basis = basis_y * (std::f64::consts::PI * i as f64 * x as f64 / width as f64).cos();
+                    let offset = (y * width as usize + x) * 3;
+
+                    r += basis * srgb_to_linear(blob[offset]);
+                    g += basis * srgb_to_linear(blob[offset + 1]);
+                    b += basis * srgb_to_linear(blob[offset + 2]);
+                }
+            }
+
+            let scale = normalisation / (width as f64 * height as f64);
+            factors[j * components_x as usize + i] = [r * scale, g * scale, b * scale];
+        }
+    }
+
+    let dc = factors[0];
+    let ac = &factors[1..];
+
+    let mut hash = String::new();
+
+    // size flag: component counts
+    let size_flag = (components_x - 1) as usize + (components_y - 1) as usize * 9;
+    hash.push_str(&encode_base83(size_flag as u32, 1));
+
+    // maximum AC component, quantized
+    let max_ac = ac.iter()
+        .flat_map(|c| c.iter())
+        .cloned()
+        .fold(0_f64, |acc, v| acc.max(v.abs()));
+
+    let quantized_max_ac = if ac.is_empty() {
+        0
+    } else {
+        ((max_ac * 166. - 0.5).floor() as i32).clamp(0, 82) as u32
+    };
+    hash.push_str(&encode_base83(quantized_max_ac, 1));
+
+    // DC component: average color, encoded back to sRGB
+    let dc_value = (linear_to_srgb(dc[0]) << 16) | (linear_to_srgb(dc[1]) << 8) | linear_to_srgb(dc[2]);
+    hash.push_str(&encode_base83(dc_value, 4));
+
+    // AC components: quantized against the maximum AC magnitude
+    let max_ac_value = if quantized_max_ac == 0 { 1. } else { (quantized_max_ac + 1) as f64 / 166. };
+    for component in ac {
+        let value = component.iter()
+            .map(|&v| quantize_ac(v, max_ac_value))
+            .fold(0_u32, |acc, q| acc * 19 + q);
+
+        hash.push_str(&encode_base83(value, 2));
+    }
+
+    Ok(hash)
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let c = value as f64 / 255.;
+
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let c = value.clamp(0., 1.);
+    let v = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    };
+
+    (v * 255. + 0.5).round().clamp(0., 255.) as u32
+}
+
+fn quantize_ac(value: f64, max_value: f64) -> u32 {
+    let v = (value / max_value).clamp(-1., 1.);
+
+    (v * 9. + 9.5).floor().clamp(0., 18.) as u32
+}
+
+fn encode_base83(value: u32, length: usize) -> String {
+    let mut result = vec![0_u8; length];
+    let mut value = value;
+
+    for i in (0..length).rev() {
+        let digit = value % 83;
+        result[i] = BLURHASH_ALPHABET[digit as usize];
+        value /= 83;
+    }
+
+    String::from_utf8(result).unwrap()
+}
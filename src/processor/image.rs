@@ -4,22 +4,35 @@ use std::fs;
 use std::mem::swap;
 use std::ops::Add;
 use std::path::{Path, PathBuf};
-use std::sync::Once;
+use std::sync::{Arc, Once};
 
 use regex::Regex;
 use anyhow::{Result, anyhow};
 use chrono::{Datelike, DateTime, Local, NaiveDateTime, TimeZone};
 use magick_rust::{MagickWand, bindings, magick_wand_genesis};
+use serde::Serialize;
 
 use crate::config::{Command, Config, Format, Quality, Resize};
 use crate::processor::avif;
-use crate::processor::exif::{GpsInfo, Metadata};
+use crate::processor::exif::{self, GpsInfo, Metadata};
+use crate::processor::geotag::{self, GeotagConfig};
+use crate::processor::heif;
+use crate::processor::raw;
+use crate::processor::remote::{Sink, Source};
+use crate::processor::template::{TemplateFields, TemplateRun};
+use crate::processor::webp;
 
 static START: Once = Once::new();
 
-pub(crate) fn prelude() {
+pub(crate) fn prelude(workers: usize) {
     START.call_once(|| {
         magick_wand_genesis();
+
+        // each worker still builds its own MagickWand per call, this only
+        // bounds how many run at once
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(workers).build_global() {
+            eprintln!("Failed to configure {} worker threads, falling back to rayon's default: {}", workers, e);
+        }
     });
 }
 
@@ -27,6 +40,7 @@ pub struct Statistics {
     pub skipped: usize,
     pub copying: usize,
     pub converted: usize,
+    pub remote_stored: usize,
     pub converted_statistics: ConvertedStatistics,
 }
 
@@ -36,13 +50,16 @@ impl Statistics {
             skipped: 0,
             copying: 0,
             converted: 0,
+            remote_stored: 0,
             converted_statistics: ConvertedStatistics {
                 resized: 0,
                 adjust_quality: 0,
                 converted_to_jpeg: 0,
                 converted_to_heic: 0,
                 converted_to_avif: 0,
+                converted_to_webp: 0,
                 gps_added: 0,
+                blur_hashed: 0,
             },
         }
     }
@@ -56,6 +73,7 @@ impl Add for Statistics {
             skipped: self.skipped + rhs.skipped,
             copying: self.copying + rhs.copying,
             converted: self.converted + rhs.converted,
+            remote_stored: self.remote_stored + rhs.remote_stored,
             converted_statistics: self.converted_statistics + rhs.converted_statistics,
         }
     }
@@ -67,7 +85,9 @@ pub struct ConvertedStatistics {
     pub converted_to_jpeg: usize,
     pub converted_to_heic: usize,
     pub converted_to_avif: usize,
+    pub converted_to_webp: usize,
     pub gps_added: usize,
+    pub blur_hashed: usize,
 }
 
 impl Add for ConvertedStatistics {
@@ -80,7 +100,9 @@ impl Add for ConvertedStatistics {
             converted_to_jpeg: self.converted_to_jpeg + rhs.converted_to_jpeg,
             converted_to_heic: self.converted_to_heic + rhs.converted_to_heic,
             converted_to_avif: self.converted_to_avif + rhs.converted_to_avif,
+            converted_to_webp: self.converted_to_webp + rhs.converted_to_webp,
             gps_added: self.gps_added + rhs.gps_added,
+            blur_hashed: self.blur_hashed + rhs.blur_hashed,
         }
     }
 }
@@ -92,48 +114,85 @@ pub enum ProcessState {
     Rewriting(String, String, String),
 }
 
+// one record per input file, describing what `process` did (or, for a dry
+// run, would have done) to it; serializable so a whole clone can be written
+// out as a diffable, machine-readable manifest instead of only a terminal log
+#[derive(Serialize, Debug)]
+pub struct ConversionRecord {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub input_format: String,
+    pub target_format: Option<String>,
+    pub operations: Vec<Operation>,
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    Resized { width: usize, height: usize },
+    QualityAdjusted { quality: u8 },
+    GpsAdded,
+    Copied,
+    SkippedExisting,
+    GeoFiltered,
+    BlurHashed { hash: String },
+}
+
 pub fn process<F>(conf: &Config, in_file: &Path, out_dir: &Path,
+                  source: &dyn Source, sink: &dyn Sink,
                   inspection: &Inspection, gps_info: Option<GpsInfo>,
-                  dry_run: bool, when_update: F) -> Result<Statistics>
+                  geotag_gpx: Option<Arc<Vec<u8>>>,
+                  dry_run: bool, template_run: &TemplateRun, when_update: F) -> Result<(Statistics, ConversionRecord)>
     where
-        F: Fn(ProcessState)
+        F: Fn(ProcessState) + Sync
 {
-    prelude();
+    prelude(conf.workers());
 
     let mut statistics = Statistics::new();
+    let mut operations = Vec::new();
 
-    let taken_at = inspection.taken_at;
-
-    let out_dir = out_dir
-        .join(taken_at.year().to_string())
-        .join(format!("{:04}-{:02}-{:02}", taken_at.year(), taken_at.month(), taken_at.day()));
+    let templated = conf.output_template().is_some();
+    let (out_dir, dest_stem) = destination_base(conf, out_dir, in_file, inspection, template_run)?;
 
-    fs::create_dir_all(&out_dir)?;
+    sink.mkdir_all(&out_dir)?;
 
     let cmd = conf.command(inspection.rating);
     let in_path_str = in_file.file_name().unwrap().to_str().unwrap();
 
     // process command
-    let save_opt = save_option_by_command(cmd, inspection, gps_info)?;
-    if let Some(rewrite_info) = save_opt {
+    let save_opt = save_option_by_command(cmd, inspection, gps_info, geotag_gpx.is_some())?;
+    let (destination, target_format) = if let Some(rewrite_info) = save_opt {
+        let target_format = rewrite_info.target_format.clone();
+
         loop {
             // determine file path according to rewrite info
-            let out_path_string = out_path(in_file, &out_dir, rewrite_info.target_format.clone())?;
+            let out_path_string = dest_path(&out_dir, &dest_stem, in_file, rewrite_info.target_format.clone())?;
+            let out_path_string = reserve_if_templated(template_run, templated, out_path_string);
             let out_path = Path::new(&out_path_string);
             let out_filename_str = out_path.file_name().unwrap().to_str().unwrap();
 
-            if out_path.exists() {
+            if sink.exists(out_path) {
                 statistics.skipped += 1;
-                break;
+                operations.push(Operation::SkippedExisting);
+                break out_path_string;
             }
 
             let mut wand = MagickWand::new();
+            let is_raw = raw::is_raw_file(in_file);
+
+            // non-RAW files go through `source` rather than reading the path
+            // directly, so the same pipeline works whether `in_file` lives on
+            // the local disk or on a remote SFTP server; RAW files are decoded
+            // straight from the local path
+            when_update(ProcessState::Reading(String::from(in_path_str)));
+            let mut blob = if is_raw {
+                raw::decode_to_blob(in_file)?
+            } else {
+                source.read_blob(in_file)?
+            };
 
             if let Some(ref gps_info) = rewrite_info.gps_info {
-                // read image fom file to blob
-                when_update(ProcessState::Reading(String::from(in_path_str)));
-                let mut blob = read_image_to_blob(in_file)?;
-
                 // adding gps
                 when_update(ProcessState::AddingGps(String::from(in_path_str)));
                 let mut other_blob = add_gps_info_to_blob(&blob, gps_info)?;
@@ -141,12 +200,27 @@ pub fn process<F>(conf: &Config, in_file: &Path, out_dir: &Path,
                 drop(other_blob);
 
                 statistics.converted_statistics.gps_added += 1;
+                operations.push(Operation::GpsAdded);
+            } else if let Some(ref gpx_bytes) = geotag_gpx {
+                // --geotag-gpx: match this photo's own DateTimeOriginal
+                // straight against the track, camera-clock-aware, instead of
+                // going through the bucketed GpsSearch/GeoCache match above
+                when_update(ProcessState::AddingGps(String::from(in_path_str)));
+                let (other_blob, added) = geotag_blob(&blob, gpx_bytes, &GeotagConfig::default())?;
 
-                // re-read from blob
-                wand.read_image_blob(&blob)?;
-            } else {
-                when_update(ProcessState::Reading(String::from(in_path_str)));
-                wand.read_image(in_file.to_str().unwrap())?;
+                if added {
+                    blob = other_blob;
+                    statistics.converted_statistics.gps_added += 1;
+                    operations.push(Operation::GpsAdded);
+                }
+            }
+
+            wand.read_image_blob(&blob)?;
+
+            if is_raw {
+                // the decode pipeline doesn't bake in orientation, so honor
+                // the tag exiv2 read straight from the RAW container
+                wand.auto_orient();
             }
 
             // determine resize
@@ -161,12 +235,22 @@ pub fn process<F>(conf: &Config, in_file: &Path, out_dir: &Path,
 
                 wand.resize_image(width, height, bindings::FilterType_LanczosFilter);
                 statistics.converted_statistics.resized += 1;
+                operations.push(Operation::Resized { width, height });
+            }
+
+            // blurhash: a compact placeholder for previews, computed from the
+            // (possibly resized) pixels currently in `wand`
+            if let Some((components_x, components_y)) = rewrite_info.blurhash {
+                let hash = heif::blur_hash(&mut wand, components_x, components_y)?;
+                statistics.converted_statistics.blur_hashed += 1;
+                operations.push(Operation::BlurHashed { hash });
             }
 
             // quality
             if let Some(percentage) = rewrite_info.quality {
                 wand.set_image_compression_quality(percentage as usize)?;
                 statistics.converted_statistics.adjust_quality += 1;
+                operations.push(Operation::QualityAdjusted { quality: percentage });
             } else if let Some(ref _target_format) = rewrite_info.target_format {
                 wand.set_image_compression_quality(95)?; // set compression quality to 95, because default value is 92
             }
@@ -188,15 +272,21 @@ pub fn process<F>(conf: &Config, in_file: &Path, out_dir: &Path,
                     }
                 }
 
-                rewrite_image(&mut wand, &rewrite_info, &out_path_string)?;
+                let encoded = rewrite_image(&mut wand, &rewrite_info, &blob, is_raw, conf.avif_threads())?;
+                sink.write_blob(out_path, &encoded)?;
                 statistics.converted += 1;
 
+                if sink.is_object_store() {
+                    statistics.remote_stored += 1;
+                }
+
                 match rewrite_info.target_format {
                     Some(ref format) => {
                         match format.as_str() {
                             JPEG_FORMAT => statistics.converted_statistics.converted_to_jpeg += 1,
                             HEIC_FORMAT => statistics.converted_statistics.converted_to_heic += 1,
                             AVIF_FORMAT => statistics.converted_statistics.converted_to_avif += 1,
+                            WEBP_FORMAT => statistics.converted_statistics.converted_to_webp += 1,
                             _ => ()
                         }
                     }
@@ -204,36 +294,123 @@ pub fn process<F>(conf: &Config, in_file: &Path, out_dir: &Path,
                 }
             }
 
-            break;
+            break out_path_string;
         }
     } else {
-        // just copying
-        if !dry_run {
-            // just copying
-            let out_path = out_path(in_file, &out_dir, None)?;
-            let out_path = Path::new(&out_path);
-            let out_path_str = out_path.file_name().unwrap().to_str().unwrap();
-
-            if !out_path.exists() {
-                when_update(ProcessState::JustCopying(
-                    String::from(in_path_str),
-                    String::from(out_path_str)));
-
-                fs::copy(in_file, out_path)?;
-                statistics.copying += 1;
-            } else {
-                statistics.skipped += 1;
+        // just copying: compute the destination up front so dry-run produces
+        // the same {source, destination} mapping it would for a real run
+        let out_path_string = dest_path(&out_dir, &dest_stem, in_file, None)?;
+        let out_path_string = reserve_if_templated(template_run, templated, out_path_string);
+        let out_path = Path::new(&out_path_string);
+        let out_path_str = out_path.file_name().unwrap().to_str().unwrap();
+
+        if sink.exists(out_path) {
+            statistics.skipped += 1;
+            operations.push(Operation::SkippedExisting);
+        } else if !dry_run {
+            when_update(ProcessState::JustCopying(
+                String::from(in_path_str),
+                String::from(out_path_str)));
+
+            let blob = source.read_blob(in_file)?;
+            sink.write_blob(out_path, &blob)?;
+            statistics.copying += 1;
+            operations.push(Operation::Copied);
+
+            if sink.is_object_store() {
+                statistics.remote_stored += 1;
             }
         } else {
             statistics.skipped += 1;
+            operations.push(Operation::Copied);
         }
-    }
 
-    Ok(statistics)
+        (out_path_string, None)
+    };
+
+    Ok((statistics, ConversionRecord {
+        source: in_file.to_path_buf(),
+        destination: PathBuf::from(destination),
+        input_format: inspection.format.clone(),
+        target_format,
+        operations,
+        dry_run,
+    }))
+}
+
+// what `process` would do to this file, decided the same way `process` does
+// but without ever calling `source.read_blob`/`sink.write_blob`/`sink.mkdir_all`
+// - the read-only counterpart `--plan` previews a whole clone against
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanAction {
+    Copy,
+    Convert(String),
+    AddGps,
+    SkipExisting,
+    GeoFiltered,
+}
+
+impl std::fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlanAction::Copy => write!(f, "copy"),
+            PlanAction::Convert(format) => write!(f, "convert to {}", format),
+            PlanAction::AddGps => write!(f, "add gps"),
+            PlanAction::SkipExisting => write!(f, "skip (already exists)"),
+            PlanAction::GeoFiltered => write!(f, "skip (outside geo filter)"),
+        }
+    }
 }
 
+pub fn plan_destination(conf: &Config, in_file: &Path, out_dir: &Path,
+                        sink: &dyn Sink, inspection: &Inspection, gps_info: Option<GpsInfo>,
+                        template_run: &TemplateRun) -> Result<(PathBuf, PlanAction)> {
+    let templated = conf.output_template().is_some();
+    let (out_dir, dest_stem) = destination_base(conf, out_dir, in_file, inspection, template_run)?;
 
-pub fn rewrite_image<T: AsRef<str>>(wand: &mut MagickWand, rewrite_info: &ConvertInfo, out_path: T) -> Result<()> {
+    let cmd = conf.command(inspection.rating);
+    // --geotag-gpx isn't previewable here: deciding whether it would actually
+    // find a fix means reading the photo's DateTimeOriginal, which --plan
+    // deliberately avoids (it never calls `source.read_blob`)
+    let save_opt = save_option_by_command(cmd, inspection, gps_info, false)?;
+
+    let (out_path_string, action) = match save_opt {
+        Some(rewrite_info) => {
+            let out_path_string = dest_path(&out_dir, &dest_stem, in_file, rewrite_info.target_format.clone())?;
+            let out_path_string = reserve_if_templated(template_run, templated, out_path_string);
+
+            let action = if sink.exists(Path::new(&out_path_string)) {
+                PlanAction::SkipExisting
+            } else if let Some(format) = rewrite_info.target_format {
+                PlanAction::Convert(format)
+            } else {
+                PlanAction::AddGps
+            };
+
+            (out_path_string, action)
+        }
+        None => {
+            let out_path_string = dest_path(&out_dir, &dest_stem, in_file, None)?;
+            let out_path_string = reserve_if_templated(template_run, templated, out_path_string);
+
+            let action = if sink.exists(Path::new(&out_path_string)) {
+                PlanAction::SkipExisting
+            } else {
+                PlanAction::Copy
+            };
+
+            (out_path_string, action)
+        }
+    };
+
+    Ok((PathBuf::from(out_path_string), action))
+}
+
+// encode `wand`'s current image according to `rewrite_info` and return the
+// resulting bytes; callers hand these off to a `Sink` rather than writing
+// to a path directly, so the same encode path works for local and remote
+// destinations
+pub fn rewrite_image(wand: &mut MagickWand, rewrite_info: &ConvertInfo, source_blob: &Vec<u8>, oriented: bool, avif_threads: usize) -> Result<Vec<u8>> {
     let target_format = match rewrite_info.target_format {
         Some(ref format) => {
             Format::from_str(format.as_str())?
@@ -256,58 +433,135 @@ pub fn rewrite_image<T: AsRef<str>>(wand: &mut MagickWand, rewrite_info: &Conver
             };
 
             // encoding to avif
-            let encoded = avif::encode(blob, quality)?;
+            let encoded = avif::encode(blob, quality, avif_threads)?;
 
-            // write the file
-            let out_path = PathBuf::from(out_path.as_ref());
-            fs::write(out_path, encoded.avif_file)?;
+            // ravif only sees raw pixels, so none of the source EXIF/XMP
+            // survives the encode; carry it over onto the AVIF bytes
+            carry_metadata(source_blob, encoded.avif_file, wand, oriented)
+        }
+        Format::WebP => {
+            // rewrite as webp, mirroring the avif path above
+
+            // write to blob
+            let blob = wand.write_image_blob("JPEG")?;
+
+            // determine target quality: fall back to ~90 when preserving, and
+            // switch to lossless encoding at 100
+            let quality = match rewrite_info.quality {
+                Some(quality) => quality as f32,
+                None => 90.
+            };
+
+            // encoding to webp
+            let encoded = webp::encode(blob, quality)?;
+
+            // same as the AVIF case above: the webp crate only sees raw
+            // pixels, so carry the source metadata over ourselves
+            carry_metadata(source_blob, encoded.webp_file, wand, oriented)
         }
         Format::HEIC => {
             // we do auto orient for HEIC image format
             wand.auto_orient();
+
+            // encode straight through libheif rather than ImageMagick's own
+            // HEIC writer; build_plane() carries the source EXIF/XMP/ICC
+            // profiles over itself, so there's no separate carry_metadata
+            // step needed the way there is for the ravif/webp crates above
+            heif::encode(wand, rewrite_info.quality.unwrap_or(95))
+        }
+        Format::JPEG => {
+            // ImageMagick handles JPEG output fine on its own, unlike the
+            // AVIF/WebP cases above, so there's no need for a dedicated
+            // encoder crate here
+            Ok(wand.write_image_blob("JPEG")?)
+        }
+        _ => {
+            let format = wand.get_image_format()?;
+            Ok(wand.write_image_blob(&format)?)
         }
-        _ => (),
     }
-
-    wand.write_image(out_path.as_ref())?;
-    Ok(())
 }
 
-fn out_path(in_file: &Path, out_dir: &Path, format: Option<String>) -> Result<String> {
-    let filename = match in_file.file_stem() {
-        Some(stem) => stem.to_str().unwrap(),   // never failed
-        None => {
-            // never reached
-            return Err(anyhow!("Failed to find stem of file"));
-        }
-    };
+// directory to create under `out_dir` and filename stem (without extension)
+// to save this file under; `conf.output_template()` drives both when set,
+// falling back to the original `<year>/<YYYY-MM-DD>/<orig_stem>` layout
+// otherwise
+fn destination_base(conf: &Config, out_dir: &Path, in_file: &Path,
+                    inspection: &Inspection, template_run: &TemplateRun) -> Result<(PathBuf, String)> {
+    match conf.output_template() {
+        Some(template) => {
+            let fields = TemplateFields::from_inspection(inspection, in_file, template_run.next_counter());
+            let rel = crate::processor::template::expand(template, &fields)?;
+            let rel_path = Path::new(&rel);
+
+            let dir = match rel_path.parent() {
+                Some(parent) if parent != Path::new("") => out_dir.join(parent),
+                _ => out_dir.to_path_buf(),
+            };
+
+            let stem = rel_path.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow!("Output template '{}' produced an empty filename", template))?
+                .to_string();
 
-    let ext = match in_file.extension() {
-        Some(ext) => ext.to_str().unwrap(), // never failed
+            Ok((dir, stem))
+        }
         None => {
-            // never reached
-            return Err(anyhow!("Failed to find extension of file"));
+            let taken_at = inspection.taken_at;
+
+            let dir = out_dir
+                .join(taken_at.year().to_string())
+                .join(format!("{:04}-{:02}-{:02}", taken_at.year(), taken_at.month(), taken_at.day()));
+
+            let stem = match in_file.file_stem() {
+                Some(stem) => stem.to_str().unwrap().to_string(),   // never failed
+                None => {
+                    // never reached
+                    return Err(anyhow!("Failed to find stem of file"));
+                }
+            };
+
+            Ok((dir, stem))
         }
-    };
+    }
+}
 
-    let dest_filename;
+// collision handling only applies when a user-defined template is active:
+// the legacy `<year>/<YYYY-MM-DD>/<orig_stem>` layout already keys uniquely
+// off the original filename, so leaving it unreserved preserves the exact
+// skip-if-exists resume behavior across separate runs
+fn reserve_if_templated(template_run: &TemplateRun, templated: bool, out_path_string: String) -> String {
+    if !templated {
+        return out_path_string;
+    }
+
+    template_run.reserve(PathBuf::from(out_path_string))
+        .to_str().unwrap().to_string()
+}
 
-    match format {
+fn dest_path(out_dir: &Path, stem: &str, in_file: &Path, format: Option<String>) -> Result<String> {
+    let ext = match format {
         Some(format) => {
             let mut dest_ext = String::from(format).to_lowercase();
             if dest_ext == "jpeg" {
                 dest_ext = String::from("jpg");
             }
 
-            dest_filename = format!("{}.{}", filename, dest_ext);
+            dest_ext
         }
         None => {
-            dest_filename = format!("{}.{}", filename, ext);
+            match in_file.extension() {
+                Some(ext) => ext.to_str().unwrap().to_string(), // never failed
+                None => {
+                    // never reached
+                    return Err(anyhow!("Failed to find extension of file"));
+                }
+            }
         }
-    }
+    };
 
     let out_path = out_dir.to_path_buf()
-        .join(&dest_filename);
+        .join(format!("{}.{}", stem, ext));
 
     Ok(String::from(out_path.to_str().unwrap()))    // never failed
 }
@@ -315,11 +569,14 @@ fn out_path(in_file: &Path, out_dir: &Path, format: Option<String>) -> Result<St
 pub const JPEG_FORMAT: &str = "jpeg";
 pub const HEIC_FORMAT: &str = "heic";
 pub const AVIF_FORMAT: &str = "avif";
+pub const WEBP_FORMAT: &str = "webp";
 
 const META_DATETIME: &str = "Exif.Image.DateTime";
 const META_RATING: &str = "Xmp.xmp.Rating";
 const META_GPS_LAT: &str = "Exif.GPSInfo.GPSLatitude";
 const META_GPS_LON: &str = "Exif.GPSInfo.GPSLongitude";
+const META_MODEL: &str = "Exif.Image.Model";
+const META_MAKE: &str = "Exif.Image.Make";
 
 pub struct Inspection {
     pub path: PathBuf,
@@ -327,6 +584,12 @@ pub struct Inspection {
     pub gps_recorded: bool,
     pub taken_at: DateTime<Local>,
     pub rating: i8,
+    // camera model the shot was taken with, when the source (RAW or
+    // otherwise) carries that tag; exiv2 reads it straight off the
+    // container the same way it does `META_DATETIME`
+    pub camera_model: Option<String>,
+    // camera manufacturer, same provenance as `camera_model`
+    pub camera_make: Option<String>,
 }
 
 pub fn inspect_image_from_path(path: &Path) -> Result<Inspection> {
@@ -335,6 +598,8 @@ pub fn inspect_image_from_path(path: &Path) -> Result<Inspection> {
         META_RATING,
         META_GPS_LAT,
         META_GPS_LON,
+        META_MODEL,
+        META_MAKE,
     ];
 
     // get metadata from path
@@ -353,26 +618,38 @@ pub fn inspect_image_from_path(path: &Path) -> Result<Inspection> {
         }
     }
 
-    // get format
-    let format = match mime.as_str() {
-        "image/jpeg" => JPEG_FORMAT,
-        "image/avif" => AVIF_FORMAT,
-        "video/quicktime" => HEIC_FORMAT,
-        _ => return Err(anyhow!("Unsupported mime: {}", mime))
+    // get format: RAW camera containers are detected by extension, since their
+    // mime types vary widely by manufacturer
+    let format = if raw::is_raw_file(path) {
+        raw::RAW_FORMAT
+    } else {
+        match mime.as_str() {
+            "image/jpeg" => JPEG_FORMAT,
+            "image/avif" => AVIF_FORMAT,
+            "video/quicktime" => HEIC_FORMAT,
+            _ => return Err(anyhow!("Unsupported mime: {}", mime))
+        }
     };
 
-    // get gps recorded
-    let lat_recorded = match tags.get(META_GPS_LAT) {
-        Some(s) => s.len() > 0,
-        None => false,
-    };
+    // get gps recorded: for JPEG, read the GPS IFD straight out of the file
+    // bytes with the pure-Rust reader, the same one `write_gps_info` writes
+    // with - RAW/HEIC containers aren't JPEG/TIFF, so they keep going
+    // through exiv2's generic tag lookup
+    let gps_recorded = if format == JPEG_FORMAT {
+        exif::is_geotagged(&fs::read(path)?)
+    } else {
+        let lat_recorded = match tags.get(META_GPS_LAT) {
+            Some(s) => s.len() > 0,
+            None => false,
+        };
 
-    let lon_recorded = match tags.get(META_GPS_LON) {
-        Some(s) => s.len() > 0,
-        None => false,
-    };
+        let lon_recorded = match tags.get(META_GPS_LON) {
+            Some(s) => s.len() > 0,
+            None => false,
+        };
 
-    let gps_recorded = lat_recorded && lon_recorded;
+        lat_recorded && lon_recorded
+    };
 
     // get taken at
     let taken_at;
@@ -398,54 +675,51 @@ pub fn inspect_image_from_path(path: &Path) -> Result<Inspection> {
         }
     }
 
+    // get camera model/make
+    let camera_model = tags.get(META_MODEL)
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let camera_make = tags.get(META_MAKE)
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
     Ok(Inspection {
         path: path.to_path_buf(),
         format: format.to_string(),
         gps_recorded,
         taken_at,
         rating,
+        camera_model,
+        camera_make,
     })
 }
 
-fn read_image_to_blob(path: &Path) -> Result<Vec<u8>> {
-    let wand = MagickWand::new();
-    let path_str = match path.to_str() {
-        Some(p) => p,
-        None => {
-            // never reached
-            return Err(anyhow!("Invalid path to have incompatible UTF-8"));
-        }
-    };
-
-    // read image from file
-    wand.read_image(path_str)?;
-
-    // get file format
-    let format = wand.get_image_format()?;
-
-    // write image to blob
-    match wand.write_image_blob(&format) {
-        Ok(blob) => Ok(blob),
-        Err(e) => {
-            Err(anyhow!("Failed to write image to blob: {}", e))
-        }
-    }
-}
-
 pub struct ConvertInfo {
     pub resize: Resize,
     pub quality: Option<u8>,
     pub target_format: Option<String>,
     pub gps_info: Option<GpsInfo>,
+    pub blurhash: Option<(u8, u8)>,
 }
 
-fn save_option_by_command(cmd: &Command, inspection: &Inspection, gps_info: Option<GpsInfo>) -> Result<Option<ConvertInfo>> {
-    let (resize, format, quality) = match cmd {
-        Command::Convert { resize, format, quality } => {
-            (resize, format, quality)
+fn save_option_by_command(cmd: &Command, inspection: &Inspection, gps_info: Option<GpsInfo>, has_geotag_gpx: bool) -> Result<Option<ConvertInfo>> {
+    let (resize, format, quality, blurhash) = match cmd {
+        Command::Convert { resize, format, quality, blurhash } => {
+            (resize, format, quality, blurhash)
         }
         Command::ByPass => {
-            return if inspection.gps_recorded || gps_info.is_none() {
+            // RAW inputs have no safe lossless passthrough, so default to JPEG
+            // even when no policy explicitly asked for a conversion
+            return if inspection.format.as_str() == raw::RAW_FORMAT {
+                Ok(Some(ConvertInfo {
+                    resize: Resize::Preserve,
+                    quality: None,
+                    target_format: Some(JPEG_FORMAT.to_string()),
+                    gps_info,
+                    blurhash: None,
+                }))
+            } else if inspection.gps_recorded || (gps_info.is_none() && !has_geotag_gpx) {
                 Ok(None)
             } else {
                 Ok(Some(ConvertInfo {
@@ -453,6 +727,7 @@ fn save_option_by_command(cmd: &Command, inspection: &Inspection, gps_info: Opti
                     quality: None,
                     target_format: None,
                     gps_info,
+                    blurhash: None,
                 }))
             };
         }
@@ -474,6 +749,9 @@ fn save_option_by_command(cmd: &Command, inspection: &Inspection, gps_info: Opti
         Format::JPEG if inspection.format.as_str() != JPEG_FORMAT => Some(JPEG_FORMAT.to_string()),
         Format::HEIC if inspection.format.as_str() != HEIC_FORMAT => Some(HEIC_FORMAT.to_string()),
         Format::AVIF if inspection.format.as_str() != AVIF_FORMAT => Some(AVIF_FORMAT.to_string()),
+        Format::WebP if inspection.format.as_str() != WEBP_FORMAT => Some(WEBP_FORMAT.to_string()),
+        // there's no safe lossless passthrough for RAW, so preserve falls back to JPEG
+        Format::Preserve if inspection.format.as_str() == raw::RAW_FORMAT => Some(JPEG_FORMAT.to_string()),
         _ => None
     };
 
@@ -482,6 +760,7 @@ fn save_option_by_command(cmd: &Command, inspection: &Inspection, gps_info: Opti
         quality,
         target_format: convert,
         gps_info,
+        blurhash: *blurhash,
     }))
 }
 
@@ -514,14 +793,104 @@ fn determine_resize(img_width: usize, img_height: usize, resize: &Resize) -> Opt
             Some((width, height))
         }
 
+        Resize::Box(box_width, box_height) => {
+            let (box_width, box_height) = (*box_width as usize, *box_height as usize);
+
+            if box_width >= img_width && box_height >= img_height {
+                return None;
+            }
+
+            let scale_factor = f64::min(
+                box_width as f64 / img_width as f64,
+                box_height as f64 / img_height as f64,
+            );
+
+            let width = (img_width as f64 * scale_factor).round() as usize;
+            let height = (img_height as f64 * scale_factor).round() as usize;
+
+            Some((width, height))
+        }
+
+        Resize::LongEdgeIfLarger(long_edge) => {
+            let long_edge = *long_edge as usize;
+            let orig_long_edge = usize::max(img_width, img_height);
+
+            if long_edge >= orig_long_edge {
+                return None;
+            }
+
+            let scale_factor = long_edge as f64 / orig_long_edge as f64;
+
+            let width = (img_width as f64 * scale_factor).round() as usize;
+            let height = (img_height as f64 * scale_factor).round() as usize;
+
+            Some((width, height))
+        }
+
+        Resize::Width(target_width) => {
+            let target_width = *target_width as usize;
+
+            if target_width >= img_width {
+                return None;
+            }
+
+            let scale_factor = target_width as f64 / img_width as f64;
+            let height = (img_height as f64 * scale_factor).round() as usize;
+
+            Some((target_width, height))
+        }
+
+        Resize::Height(target_height) => {
+            let target_height = *target_height as usize;
+
+            if target_height >= img_height {
+                return None;
+            }
+
+            let scale_factor = target_height as f64 / img_height as f64;
+            let width = (img_width as f64 * scale_factor).round() as usize;
+
+            Some((width, target_height))
+        }
+
         Resize::Preserve => None,
     }
 }
 
 fn add_gps_info_to_blob(blob: &Vec<u8>, gps_info: &GpsInfo) -> Result<Vec<u8>> {
+    exif::write_gps_info(blob, gps_info)
+}
+
+// backs `--geotag-gpx`: reads this photo's own `DateTimeOriginal` out of
+// `blob` and matches it against `gpx_blob` via `geotag::geotag_photo`,
+// returning the (possibly) geotagged blob and whether a position was found
+fn geotag_blob(blob: &Vec<u8>, gpx_blob: &[u8], config: &GeotagConfig) -> Result<(Vec<u8>, bool)> {
     let meta = Metadata::new_from_blob(blob)?;
-    meta.add_gps_info(gps_info)?;
-    meta.paste_to_blob(blob)
+
+    match geotag::geotag_photo(gpx_blob, &meta, config)? {
+        Some(gps_info) => Ok((exif::write_gps_info(blob, &gps_info)?, true)),
+        None => Ok((blob.clone(), false)),
+    }
+}
+
+// AVIF/WebP go through pixel-only encoders (`avif::encode`/`webp::encode`)
+// instead of ImageMagick's own writer, so `encoded` comes back with none of
+// `source_blob`'s EXIF/XMP; read that metadata back in and paste it onto
+// `encoded`. Pixel dimensions are rewritten to match `wand`'s current (post-
+// resize) size, and when `oriented` says the pixels were already rotated to
+// match a non-identity Orientation (e.g. a RAW decode), the tag is reset to
+// 1 so viewers don't rotate the image a second time.
+fn carry_metadata(source_blob: &Vec<u8>, encoded: Vec<u8>, wand: &MagickWand, oriented: bool) -> Result<Vec<u8>> {
+    let meta = Metadata::new_from_blob(source_blob)?;
+
+    meta.set_tag("Exif.Photo.PixelXDimension", wand.get_image_width().to_string())?;
+    meta.set_tag("Exif.Photo.PixelYDimension", wand.get_image_height().to_string())?;
+
+    if oriented {
+        meta.set_tag("Exif.Image.Orientation", "1")?;
+    }
+
+    meta.paste_to_blob(&encoded)
 }
 
 #[allow(dead_code)]
@@ -1,17 +1,28 @@
 pub mod image;
+pub mod exif;
 pub mod gps;
+pub mod geotag;
+pub mod heif;
+pub mod raw;
+pub mod remote;
+pub mod avif;
+pub mod template;
+pub mod webp;
 
 use std::ops::Add;
-use std::path::Path;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use console::style;
 use anyhow::{Result, Error, anyhow};
 use chrono::{DateTime, FixedOffset, Local};
+use gpx::Waypoint;
 
 use crate::config::Config;
 use crate::processor::gps::GpsSearch;
-use crate::processor::image::{HEIC_FORMAT, Inspection, ProcessState, Statistics as ImageStatistics};
+use crate::processor::image::{ConversionRecord, HEIC_FORMAT, Inspection, Operation, ProcessState, Statistics as ImageStatistics};
+use crate::processor::remote::{Sink, Source};
+use crate::processor::template::TemplateRun;
 
 pub struct CloneStatistics {
     pub total_cloned: usize,
@@ -51,19 +62,25 @@ impl CloneStatistics {
             println!("{:>width$} just copied", image_stat.copying);
             println!("{:>width$} skipped", image_stat.skipped);
             println!("{:>width$} converted", image_stat.converted);
+            println!("{:>width$} stored remotely", image_stat.remote_stored);
 
             let converted_stat = &image_stat.converted_statistics;
 
             let inner_width = max_width(vec![converted_stat.resized,
                                              converted_stat.adjust_quality,
                                              converted_stat.converted_to_heic,
-                                             converted_stat.converted_to_jpeg]);
+                                             converted_stat.converted_to_jpeg,
+                                             converted_stat.converted_to_avif,
+                                             converted_stat.converted_to_webp]);
 
             println!("{:>width$} {:>inner_width$} gps added", style("-").yellow(), converted_stat.gps_added);
             println!("{:>width$} {:>inner_width$} resized", style("-").yellow(), converted_stat.resized);
             println!("{:>width$} {:>inner_width$} adjusted quality", style("-").yellow(), converted_stat.adjust_quality);
             println!("{:>width$} {:>inner_width$} converted to HEIC", style("-").yellow(), converted_stat.converted_to_heic);
             println!("{:>width$} {:>inner_width$} converted to JPEG", style("-").yellow(), converted_stat.converted_to_jpeg);
+            println!("{:>width$} {:>inner_width$} converted to AVIF", style("-").yellow(), converted_stat.converted_to_avif);
+            println!("{:>width$} {:>inner_width$} converted to WebP", style("-").yellow(), converted_stat.converted_to_webp);
+            println!("{:>width$} {:>inner_width$} blurhash generated", style("-").yellow(), converted_stat.blur_hashed);
         }
 
         // print errors
@@ -118,28 +135,30 @@ pub enum CloneState {
     Converting(String, String, String),
 }
 
-pub fn clone_image<'a, F>(conf: &Config,
-                          in_file: &Path, out_dir: &Path,
-                          inspection: &Inspection,
-                          gpx: Rc<Box<dyn GpsSearch + 'a>>,
-                          dry_run: bool,
-                          when_update: F) -> Result<CloneStatistics>
+pub fn clone_image<F>(conf: &Config,
+                      in_file: &Path, out_dir: &Path,
+                      source: &dyn Source, sink: &dyn Sink,
+                      inspection: &Inspection,
+                      gpx: Arc<Box<dyn GpsSearch>>,
+                      geotag_gpx: Option<Arc<Vec<u8>>>,
+                      dry_run: bool,
+                      template_run: &TemplateRun,
+                      when_update: F) -> Result<(CloneStatistics, ConversionRecord, Option<Waypoint>)>
     where
-        F: Fn(CloneState)
+        F: Fn(CloneState) + Sync
 {
     let mut statistics = CloneStatistics::new();
 
-    // check arguments
-    if !in_file.is_file() {
-        return Err(anyhow!("Input path '{}' is not file", in_file.to_str().unwrap()));
-    }
-
-    if !out_dir.is_dir() {
-        return Err(anyhow!("Output path '{}' is not directory", in_file.to_str().unwrap()));
+    // check arguments: go through `source`/`sink` rather than `Path::is_file`/
+    // `Path::is_dir` so this works whether the paths live on the local disk
+    // or on a remote SFTP server
+    if !sink.exists(out_dir) {
+        return Err(anyhow!("Output path '{}' is not directory", out_dir.to_str().unwrap()));
     }
 
     // retrieve gps data
     let mut gps_info = None;
+    let mut matched_waypoint = None;
     if !inspection.gps_recorded && inspection.format != HEIC_FORMAT {
         // try to match gps
         // currently, EXIV2 the library to manipulate EXIF under hood is not support HEIF/HEIC
@@ -152,13 +171,34 @@ pub fn clone_image<'a, F>(conf: &Config,
                 lon: waypoint.point().x(),
                 alt: waypoint.elevation.unwrap_or(0.0),
             });
+            matched_waypoint = Some(waypoint);
         } else {
             gps_info = None
         }
     }
 
+    // reject photos outside the user-specified area, if one was configured;
+    // a photo that couldn't be matched against any waypoint at all can't be
+    // confirmed to be inside the area either, so it's rejected too
+    if let Some(filter) = conf.geo_filter() {
+        let passes = matched_waypoint.as_ref().map_or(false, |w| filter.matches(w));
+
+        if !passes {
+            let record = ConversionRecord {
+                source: in_file.to_path_buf(),
+                destination: PathBuf::new(),
+                input_format: inspection.format.clone(),
+                target_format: None,
+                operations: vec![Operation::GeoFiltered],
+                dry_run,
+            };
+
+            return Ok((statistics, record, None));
+        }
+    }
+
     // try to process command to manipulate image
-    match image::process(conf, in_file, out_dir, &inspection, gps_info, dry_run, |state| {
+    match image::process(conf, in_file, out_dir, source, sink, &inspection, gps_info, geotag_gpx, dry_run, template_run, |state| {
         match state {
             ProcessState::Reading(in_path) => {
                 when_update(CloneState::Reading(in_path));
@@ -174,16 +214,53 @@ pub fn clone_image<'a, F>(conf: &Config,
             }
         }
     }) {
-        Ok(image_stat) => {
+        Ok((image_stat, record)) => {
             statistics.total_cloned += 1;
             statistics.image = Some(image_stat);
+
+            Ok((statistics, record, matched_waypoint))
         }
         Err(e) => {
-            return Err(anyhow!("Failed to process image: {}", e.to_string()));
+            Err(anyhow!("Failed to process image: {}", e.to_string()))
+        }
+    }
+}
+
+// what `clone_image` would do to this file, decided through the same gps-
+// match/geo-filter/format logic but never reading or writing image bytes;
+// backs the `--plan` preview so it can show the exact destination and action
+// the real clone would take, including files that would be skipped outright
+pub fn plan_clone_item(conf: &Config,
+                       in_file: &Path, out_dir: &Path,
+                       sink: &dyn Sink,
+                       inspection: &Inspection,
+                       gpx: Arc<Box<dyn GpsSearch>>,
+                       template_run: &TemplateRun) -> Result<(PathBuf, image::PlanAction)> {
+    let mut gps_info = None;
+    let mut matched_waypoint = None;
+    if !inspection.gps_recorded && inspection.format != HEIC_FORMAT {
+        let gpx = gpx.clone();
+        let taken_at = inspection.taken_at.to_fixed_offset();
+
+        if let Some(waypoint) = gpx.search(&taken_at) {
+            gps_info = Some(image::GpsInfo {
+                lat: waypoint.point().y(),
+                lon: waypoint.point().x(),
+                alt: waypoint.elevation.unwrap_or(0.0),
+            });
+            matched_waypoint = Some(waypoint);
+        }
+    }
+
+    if let Some(filter) = conf.geo_filter() {
+        let passes = matched_waypoint.as_ref().map_or(false, |w| filter.matches(w));
+
+        if !passes {
+            return Ok((PathBuf::new(), image::PlanAction::GeoFiltered));
         }
     }
 
-    Ok(statistics)
+    image::plan_destination(conf, in_file, out_dir, sink, inspection, gps_info, template_run)
 }
 
 trait ToFixedOffset {
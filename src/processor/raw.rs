@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+pub const RAW_FORMAT: &str = "raw";
+
+const RAW_EXTENSIONS: &[&str] = &[
+    "dng", "arw", "nef", "nrw", "cr2", "cr3", "rw2", "raf", "orf", "pef", "srw", "raw",
+];
+
+pub fn is_raw_file(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => RAW_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// decode a RAW sensor file into an in-memory PPM blob so the rest of the
+// pipeline can hand it to `MagickWand::read_image_blob` unchanged. `rawloader`/
+// `imagepipe` pull in their own demosaicing math and aren't needed by anyone
+// who only ever imports JPEG/HEIC, so the decode itself lives behind the
+// `raw` feature; `is_raw_file` above stays unconditional so the walker can
+// still recognize (and, with the feature off, cleanly reject) these files.
+#[cfg(feature = "raw")]
+pub fn decode_to_blob(path: &Path) -> Result<Vec<u8>> {
+    let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+
+    // make sure the sensor data itself is readable before building the pipeline
+    rawloader::decode_file(path_str)
+        .map_err(|e| anyhow!("Failed to decode RAW sensor data from '{}': {}", path_str, e))?;
+
+    let mut pipeline = imagepipe::Pipeline::new_from_file(path_str)
+        .map_err(|e| anyhow!("Failed to build imaging pipeline for '{}': {}", path_str, e))?;
+
+    let decoded = pipeline.output_8bit(None)
+        .map_err(|e| anyhow!("Failed to render RAW image '{}': {}", path_str, e))?;
+
+    Ok(to_ppm(decoded.width, decoded.height, &decoded.data))
+}
+
+#[cfg(not(feature = "raw"))]
+pub fn decode_to_blob(path: &Path) -> Result<Vec<u8>> {
+    Err(anyhow!("Cannot decode RAW file '{}': this build of kapy was compiled without the 'raw' feature", path.to_str().unwrap_or(".")))
+}
+
+// wrap an interleaved 8-bit RGB buffer into a binary PPM (P6) blob
+#[cfg(feature = "raw")]
+fn to_ppm(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let header = format!("P6\n{} {}\n255\n", width, height);
+
+    let mut blob = Vec::with_capacity(header.len() + rgb.len());
+    blob.extend_from_slice(header.as_bytes());
+    blob.extend_from_slice(rgb);
+
+    blob
+}
@@ -0,0 +1,615 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use oauth2::AccessToken;
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use regex::Regex;
+use serde_json::json;
+
+use crate::drive::auth::{AuthFlow, GoogleAuthenticator, ListenPort, Store};
+
+// A `Source` hands input bytes to the pipeline; a `Sink` accepts output bytes
+// and manages the destination layout. `process` is agnostic to where the
+// bytes actually live, so the same resize/convert/GPS path works whether
+// files sit on the local disk or on a remote SFTP server. Both are `Sync` so
+// `clone_image` can share one `Source`/`Sink` across the rayon pool `do_clone`
+// runs the clone loop on.
+pub trait Source: Sync {
+    fn read_blob(&self, path: &Path) -> Result<Vec<u8>>;
+}
+
+pub trait Sink: Sync {
+    fn mkdir_all(&self, path: &Path) -> Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    fn write_blob(&self, path: &Path, data: &[u8]) -> Result<()>;
+
+    // whether this sink stores objects in a remote bucket rather than a
+    // conventional directory tree, so the clone summary can report how many
+    // files ended up in object storage
+    fn is_object_store(&self) -> bool {
+        false
+    }
+}
+
+// build the `Source`/`Sink` to use for a configured import/export path: a
+// literal `sftp://user@host[:port]/path` connects over SFTP, a sink path of
+// `gs://bucket/prefix` uploads to Google Cloud Storage, anything else is
+// treated as a local filesystem path
+pub fn open_source(path: &Path) -> Result<Box<dyn Source>> {
+    match path.to_str().filter(|s| s.starts_with("sftp://")) {
+        Some(s) => {
+            let location = parse_sftp_url(s)?;
+            Ok(Box::new(SftpClient::connect(&location)?))
+        }
+        None => Ok(Box::new(LocalFs)),
+    }
+}
+
+pub fn open_sink(path: &Path, cred_path: &Path) -> Result<Box<dyn Sink>> {
+    match path.to_str() {
+        Some(s) if s.starts_with("sftp://") => {
+            let location = parse_sftp_url(s)?;
+            Ok(Box::new(SftpClient::connect(&location)?))
+        }
+        Some(s) if s.starts_with("gs://") => {
+            let location = parse_gcs_url(s)?;
+            Ok(Box::new(GcsClient::connect(location, cred_path)?))
+        }
+        _ => Ok(Box::new(LocalFs)),
+    }
+}
+
+pub struct LocalFs;
+
+impl Source for LocalFs {
+    fn read_blob(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
+}
+
+impl Sink for LocalFs {
+    fn mkdir_all(&self, path: &Path) -> Result<()> {
+        Ok(fs::create_dir_all(path)?)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn write_blob(&self, path: &Path, data: &[u8]) -> Result<()> {
+        Ok(fs::write(path, data)?)
+    }
+}
+
+// `sftp://user@host[:port]/path`
+#[derive(Debug, PartialEq)]
+pub struct SftpLocation {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+const DEFAULT_SFTP_PORT: u16 = 22;
+
+pub fn parse_sftp_url(s: &str) -> Result<SftpLocation> {
+    let re = Regex::new(r"^sftp://(?P<user>[^@]+)@(?P<host>[^:/]+)(:(?P<port>[0-9]+))?(?P<path>/.*)$")?;
+
+    let captures = re.captures(s)
+        .ok_or_else(|| anyhow!("Invalid sftp url '{}': expected sftp://user@host[:port]/path", s))?;
+
+    let port = match captures.name("port") {
+        Some(p) => p.as_str().parse::<u16>()?,
+        None => DEFAULT_SFTP_PORT,
+    };
+
+    Ok(SftpLocation {
+        user: captures.name("user").unwrap().as_str().to_string(),
+        host: captures.name("host").unwrap().as_str().to_string(),
+        port,
+        path: captures.name("path").unwrap().as_str().to_string(),
+    })
+}
+
+// `gs://bucket/prefix`
+#[derive(Debug, PartialEq)]
+pub struct GcsLocation {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+pub fn parse_gcs_url(s: &str) -> Result<GcsLocation> {
+    let re = Regex::new(r"^gs://(?P<bucket>[^/]+)(?P<prefix>/.*)?$")?;
+
+    let captures = re.captures(s)
+        .ok_or_else(|| anyhow!("Invalid gcs url '{}': expected gs://bucket/prefix", s))?;
+
+    let prefix = captures.name("prefix")
+        .map(|m| m.as_str().trim_start_matches('/').to_string())
+        .unwrap_or_default();
+
+    Ok(GcsLocation {
+        bucket: captures.name("bucket").unwrap().as_str().to_string(),
+        prefix,
+    })
+}
+
+// the URL spec's "path percent-encode set": the C0 control set plus space,
+// `"`, `#`, `<`, `>`, `?`, backtick, `{` and `}`
+const GCS_OBJECT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'<').add(b'>').add(b'?').add(b'`').add(b'{').add(b'}');
+
+// uploads processed images to a Google Cloud Storage bucket via the JSON
+// API's multipart upload, authenticated with the same OAuth client used for
+// Google Drive; GCS has no real directories, so object names are built by
+// joining the configured bucket/prefix to the path `process` would otherwise
+// have written locally
+pub struct GcsClient {
+    bucket: String,
+    access_token: AccessToken,
+}
+
+impl GcsClient {
+    pub fn connect(location: GcsLocation, cred_path: &Path) -> Result<Self> {
+        let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::File(cred_path), AuthFlow::Browser);
+        let access_token = auth.access_token()?;
+
+        Ok(Self {
+            bucket: location.bucket,
+            access_token,
+        })
+    }
+
+    fn object_name(&self, path: &Path) -> Result<String> {
+        let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+        let prefix = format!("gs://{}/", self.bucket);
+
+        Ok(path_str.strip_prefix(prefix.as_str()).unwrap_or(path_str).trim_start_matches('/').to_string())
+    }
+}
+
+fn gcs_content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase().as_str() {
+        "heic" | "heif" => "image/heic",
+        "jpg" | "jpeg" => "image/jpeg",
+        "avif" => "image/avif",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Sink for GcsClient {
+    fn mkdir_all(&self, _path: &Path) -> Result<()> {
+        // buckets have no real directory structure to create ahead of time
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let object = match self.object_name(path) {
+            Ok(object) => object,
+            Err(_) => return false,
+        };
+
+        if object.is_empty() {
+            // the bucket/prefix root itself: nothing to check for
+            return true;
+        }
+
+        let encoded = utf8_percent_encode(&object, GCS_OBJECT_ENCODE_SET).to_string();
+        let url = format!("https://storage.googleapis.com/storage/v1/b/{}/o/{}", self.bucket, encoded);
+
+        let client = reqwest::blocking::Client::new();
+        match client.get(&url).bearer_auth(self.access_token.secret()).send() {
+            Ok(res) => res.status() == reqwest::StatusCode::OK,
+            Err(_) => false,
+        }
+    }
+
+    fn write_blob(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let object = self.object_name(path)?;
+        let content_type = gcs_content_type(path);
+
+        let boundary = "kapy-gcs-upload";
+        let metadata = json!({ "name": object });
+
+        let mut body = Vec::new();
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        body.extend_from_slice(b"Content-Type: application/json; charset=UTF-8\r\n\r\n");
+        body.extend_from_slice(metadata.to_string().as_bytes());
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        body.extend_from_slice(format!("Content-Type: {}\r\n\r\n", content_type).as_bytes());
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(format!("--{}--", boundary).as_bytes());
+
+        let url = format!("https://storage.googleapis.com/upload/storage/v1/b/{}/o?uploadType=multipart", self.bucket);
+
+        let client = reqwest::blocking::Client::new();
+        let res = client.post(&url)
+            .bearer_auth(self.access_token.secret())
+            .header(reqwest::header::CONTENT_TYPE, format!("multipart/related; boundary={}", boundary))
+            .body(body)
+            .send()
+            .map_err(|e| anyhow!("Failed to upload '{}' to gs://{}: {}", object, self.bucket, e))?;
+
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to upload '{}' to gs://{}: {}", object, self.bucket, res.status()))
+        }
+    }
+
+    fn is_object_store(&self) -> bool {
+        true
+    }
+}
+
+// opaque libssh handles; see `ExifMetadataT` in `processor/exif.rs` for the
+// equivalent pattern used to wrap exiv2
+#[repr(C)]
+struct SshSessionT {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+#[repr(C)]
+struct SftpSessionT {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+#[repr(C)]
+struct SftpFileT {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+#[repr(C)]
+struct SftpDirT {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+// `sftp_attributes` in libssh's `sftp.h` has many more fields after `name`,
+// but since libssh only ever hands us a pointer into its own allocation
+// (never asks us to build one), we only need to agree on the fields we
+// actually read: `name` is the struct's first member, so this partial
+// layout is sound regardless of what follows it in memory.
+#[repr(C)]
+struct SftpAttributesT {
+    name: *mut c_char,
+}
+
+// `enum ssh_options_e` from libssh's `libssh.h`; only the members this
+// module sets are listed, since the enum's integer values are part of
+// libssh's stable ABI and the rest are irrelevant here
+#[repr(C)]
+#[allow(dead_code)]
+enum SshOption {
+    Host = 0,
+    Port = 1,
+    PortStr = 2,
+    Fd = 3,
+    User = 4,
+}
+
+// flags from libssh's `sftp.h`, which mirrors POSIX `open(2)` flags rather
+// than pulling in `libc` for four constants
+const SFTP_O_RDONLY: i32 = 0x00;
+const SFTP_O_WRONLY: i32 = 0x01;
+const SFTP_O_CREAT: i32 = 0o100;
+const SFTP_O_TRUNC: i32 = 0o1000;
+
+const SFTP_READ_CHUNK: usize = 64 * 1024;
+
+#[link(name = "ssh")]
+extern "C" {
+    fn ssh_new() -> *mut SshSessionT;
+    fn ssh_free(session: *mut SshSessionT);
+    fn ssh_options_set(session: *mut SshSessionT, option: SshOption, value: *const std::ffi::c_void) -> i32;
+    fn ssh_connect(session: *mut SshSessionT) -> i32;
+    fn ssh_disconnect(session: *mut SshSessionT);
+    fn ssh_userauth_publickey_auto(session: *mut SshSessionT, username: *const c_char, passphrase: *const c_char) -> i32;
+    fn ssh_userauth_agent(session: *mut SshSessionT, username: *const c_char) -> i32;
+    fn ssh_get_error(error: *mut SshSessionT) -> *const c_char;
+
+    fn sftp_new(session: *mut SshSessionT) -> *mut SftpSessionT;
+    fn sftp_free(sftp: *mut SftpSessionT);
+    fn sftp_init(sftp: *mut SftpSessionT) -> i32;
+
+    fn sftp_open(sftp: *mut SftpSessionT, file: *const c_char, accesstype: i32, mode: u16) -> *mut SftpFileT;
+    fn sftp_read(file: *mut SftpFileT, buf: *mut u8, count: usize) -> isize;
+    fn sftp_write(file: *mut SftpFileT, buf: *const u8, count: usize) -> isize;
+    fn sftp_close(file: *mut SftpFileT) -> i32;
+
+    fn sftp_mkdir(sftp: *mut SftpSessionT, directory: *const c_char, mode: u16) -> i32;
+    fn sftp_stat(sftp: *mut SftpSessionT, path: *const c_char) -> *mut SftpAttributesT;
+    fn sftp_attributes_free(attr: *mut SftpAttributesT);
+
+    fn sftp_opendir(sftp: *mut SftpSessionT, path: *const c_char) -> *mut SftpDirT;
+    fn sftp_readdir(sftp: *mut SftpSessionT, dir: *mut SftpDirT) -> *mut SftpAttributesT;
+    fn sftp_dir_eof(dir: *mut SftpDirT) -> i32;
+    fn sftp_closedir(dir: *mut SftpDirT) -> i32;
+}
+
+// renders the session's last error for diagnostics, e.g. after `ssh_connect`
+// or `ssh_userauth_*` fail
+unsafe fn ssh_error_string(session: *mut SshSessionT) -> String {
+    let msg = ssh_get_error(session);
+    if msg.is_null() {
+        "unknown error".to_string()
+    } else {
+        CStr::from_ptr(msg).to_string_lossy().into_owned()
+    }
+}
+
+// safe wrapper around a connected SFTP session
+pub struct SftpClient {
+    session: *mut SshSessionT,
+    sftp: *mut SftpSessionT,
+    // libssh's own handles aren't thread-safe, so every call below takes
+    // this first to serialize access instead of touching them concurrently
+    guard: Mutex<()>,
+}
+
+unsafe impl Send for SftpClient {}
+
+// sound because every method below locks `guard` before touching `session`/
+// `sftp`, so concurrent callers (e.g. `do_clone`'s rayon pool) only ever
+// drive the underlying libssh handles one at a time
+unsafe impl Sync for SftpClient {}
+
+impl Drop for SftpClient {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.sftp.is_null() {
+                sftp_free(self.sftp);
+            }
+
+            if !self.session.is_null() {
+                ssh_disconnect(self.session);
+                ssh_free(self.session);
+            }
+        }
+    }
+}
+
+impl SftpClient {
+    // connect using key-based auth when available, falling back to the
+    // running ssh-agent
+    pub fn connect(location: &SftpLocation) -> Result<Self> {
+        unsafe {
+            let session = ssh_new();
+            if session.is_null() {
+                return Err(anyhow!("Failed to create ssh session"));
+            }
+
+            let host = CString::new(location.host.as_str())?;
+            let user = CString::new(location.user.as_str())?;
+            let port = location.port as u32;
+
+            ssh_options_set(session, SshOption::Host, host.as_ptr() as *const std::ffi::c_void);
+            ssh_options_set(session, SshOption::Port, &port as *const u32 as *const std::ffi::c_void);
+            ssh_options_set(session, SshOption::User, user.as_ptr() as *const std::ffi::c_void);
+
+            if ssh_connect(session) != 0 {
+                let err = ssh_error_string(session);
+                ssh_free(session);
+                return Err(anyhow!("Failed to connect to '{}@{}:{}': {}", location.user, location.host, location.port, err));
+            }
+
+            if ssh_userauth_publickey_auto(session, std::ptr::null(), std::ptr::null()) != 0
+                && ssh_userauth_agent(session, user.as_ptr()) != 0 {
+                let err = ssh_error_string(session);
+                ssh_disconnect(session);
+                ssh_free(session);
+                return Err(anyhow!("Failed to authenticate to '{}@{}' via key or agent: {}", location.user, location.host, err));
+            }
+
+            let sftp = sftp_new(session);
+            if sftp.is_null() || sftp_init(sftp) != 0 {
+                ssh_disconnect(session);
+                ssh_free(session);
+                return Err(anyhow!("Failed to initialize sftp subsystem"));
+            }
+
+            Ok(SftpClient { session, sftp, guard: Mutex::new(()) })
+        }
+    }
+
+    // checks for an existing remote path via `sftp_stat`, freeing the
+    // attributes libssh hands back since this module never reads them
+    fn stat_exists(&self, c_path: &CString) -> bool {
+        unsafe {
+            let attr = sftp_stat(self.sftp, c_path.as_ptr());
+            if attr.is_null() {
+                false
+            } else {
+                sftp_attributes_free(attr);
+                true
+            }
+        }
+    }
+
+    // non-recursive listing of the names of every entry (files and
+    // subdirectories alike, minus `.`/`..`) directly under `dir`
+    pub fn list_files(&self, dir: &Path) -> Result<Vec<String>> {
+        let _guard = self.guard.lock().unwrap();
+
+        let dir_str = dir.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+        let c_dir = CString::new(dir_str)?;
+
+        unsafe {
+            let handle = sftp_opendir(self.sftp, c_dir.as_ptr());
+            if handle.is_null() {
+                return Err(anyhow!("Failed to open remote directory '{}'", dir_str));
+            }
+
+            let mut names = Vec::new();
+            loop {
+                let attr = sftp_readdir(self.sftp, handle);
+                if attr.is_null() {
+                    break;
+                }
+
+                let name = CStr::from_ptr((*attr).name).to_string_lossy().into_owned();
+                sftp_attributes_free(attr);
+
+                if name != "." && name != ".." {
+                    names.push(name);
+                }
+            }
+
+            let reached_eof = sftp_dir_eof(handle) != 0;
+            sftp_closedir(handle);
+
+            if !reached_eof {
+                return Err(anyhow!("Failed to list remote directory '{}'", dir_str));
+            }
+
+            Ok(names)
+        }
+    }
+}
+
+impl Source for SftpClient {
+    fn read_blob(&self, path: &Path) -> Result<Vec<u8>> {
+        let _guard = self.guard.lock().unwrap();
+
+        let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+        let c_path = CString::new(path_str)?;
+
+        unsafe {
+            let file = sftp_open(self.sftp, c_path.as_ptr(), SFTP_O_RDONLY, 0o644);
+            if file.is_null() {
+                return Err(anyhow!("Failed to open '{}' over sftp", path_str));
+            }
+
+            let mut out = Vec::new();
+            let mut chunk = [0u8; SFTP_READ_CHUNK];
+
+            loop {
+                let n = sftp_read(file, chunk.as_mut_ptr(), chunk.len());
+                if n < 0 {
+                    sftp_close(file);
+                    return Err(anyhow!("Failed to read '{}' over sftp", path_str));
+                } else if n == 0 {
+                    break;
+                }
+
+                // copies the foreign buffer into Rust-owned memory rather
+                // than ever taking ownership of a pointer libssh allocated
+                out.extend_from_slice(&chunk[..n as usize]);
+            }
+
+            sftp_close(file);
+            Ok(out)
+        }
+    }
+}
+
+impl Sink for SftpClient {
+    fn mkdir_all(&self, path: &Path) -> Result<()> {
+        let _guard = self.guard.lock().unwrap();
+
+        let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+
+        // libssh's `sftp_mkdir` only creates a single directory, so
+        // recreate `mkdir -p` by walking the path component by component
+        let mut prefix = String::new();
+        for segment in path_str.split('/').filter(|s| !s.is_empty()) {
+            prefix.push('/');
+            prefix.push_str(segment);
+
+            let c_prefix = CString::new(prefix.as_str())?;
+
+            unsafe {
+                if sftp_mkdir(self.sftp, c_prefix.as_ptr(), 0o755) != 0 && !self.stat_exists(&c_prefix) {
+                    return Err(anyhow!("Failed to create remote directory '{}'", prefix));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let _guard = self.guard.lock().unwrap();
+
+        let path_str = match path.to_str() {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let c_path = match CString::new(path_str) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+
+        self.stat_exists(&c_path)
+    }
+
+    fn write_blob(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let _guard = self.guard.lock().unwrap();
+
+        let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path to have incompatible UTF-8"))?;
+        let c_path = CString::new(path_str)?;
+
+        unsafe {
+            let file = sftp_open(self.sftp, c_path.as_ptr(), SFTP_O_WRONLY | SFTP_O_CREAT | SFTP_O_TRUNC, 0o644);
+            if file.is_null() {
+                return Err(anyhow!("Failed to open '{}' over sftp", path_str));
+            }
+
+            let mut written = 0usize;
+            while written < data.len() {
+                let n = sftp_write(file, data[written..].as_ptr(), data.len() - written);
+                if n <= 0 {
+                    sftp_close(file);
+                    return Err(anyhow!("Failed to write '{}' over sftp", path_str));
+                }
+
+                written += n as usize;
+            }
+
+            sftp_close(file);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sftp_location() {
+        let loc = parse_sftp_url("sftp://pi@camera-nas:2222/volume1/dcim").unwrap();
+        assert_eq!(loc, SftpLocation {
+            user: "pi".to_string(),
+            host: "camera-nas".to_string(),
+            port: 2222,
+            path: "/volume1/dcim".to_string(),
+        });
+
+        let loc = parse_sftp_url("sftp://pi@camera-nas/volume1/dcim").unwrap();
+        assert_eq!(loc.port, DEFAULT_SFTP_PORT);
+    }
+
+    #[test]
+    fn parse_gcs_location() {
+        let loc = parse_gcs_url("gs://my-bucket/dcim").unwrap();
+        assert_eq!(loc, GcsLocation {
+            bucket: "my-bucket".to_string(),
+            prefix: "dcim".to_string(),
+        });
+
+        let loc = parse_gcs_url("gs://my-bucket").unwrap();
+        assert_eq!(loc.prefix, "");
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::processor::image::Inspection;
+
+// the fields an output template can reference; `{token}` or `{token:width}`
+// (zero-padded to `width`) in the template string is replaced by the
+// matching field below
+pub struct TemplateFields {
+    pub year: String,
+    pub month: String,
+    pub day: String,
+    pub date: String,
+    pub camera_make: String,
+    pub camera_model: String,
+    pub orig_name: String,
+    pub orig_stem: String,
+    pub orig_ext: String,
+    pub counter: u32,
+}
+
+impl TemplateFields {
+    pub fn from_inspection(inspection: &Inspection, in_file: &Path, counter: u32) -> TemplateFields {
+        use chrono::Datelike;
+
+        let taken_at = inspection.taken_at;
+
+        let orig_stem = in_file.file_stem().and_then(|s| s.to_str()).unwrap_or("output").to_string();
+        let orig_ext = in_file.extension().and_then(|e| e.to_str()).unwrap_or("").to_string();
+        let orig_name = if orig_ext.is_empty() {
+            orig_stem.clone()
+        } else {
+            format!("{}.{}", orig_stem, orig_ext)
+        };
+
+        TemplateFields {
+            year: format!("{:04}", taken_at.year()),
+            month: format!("{:02}", taken_at.month()),
+            day: format!("{:02}", taken_at.day()),
+            date: format!("{:04}-{:02}-{:02}", taken_at.year(), taken_at.month(), taken_at.day()),
+            camera_make: inspection.camera_make.clone().unwrap_or_else(|| "unknown".to_string()),
+            camera_model: inspection.camera_model.clone().unwrap_or_else(|| "unknown".to_string()),
+            orig_name,
+            orig_stem,
+            orig_ext,
+            counter,
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<&str> {
+        match key {
+            "year" => Some(&self.year),
+            "month" => Some(&self.month),
+            "day" => Some(&self.day),
+            "date" => Some(&self.date),
+            "camera_make" => Some(&self.camera_make),
+            "camera_model" => Some(&self.camera_model),
+            "orig_name" => Some(&self.orig_name),
+            "orig_stem" => Some(&self.orig_stem),
+            "orig_ext" => Some(&self.orig_ext),
+            _ => None,
+        }
+    }
+}
+
+// a token value is dropped straight into a path component, so strip the
+// characters that would otherwise split it into one (or escape it entirely
+// on Windows)
+fn sanitize(value: &str) -> String {
+    value.chars().map(|c| if c == '/' || c == '\\' { '_' } else { c }).collect()
+}
+
+// expand `{token}`/`{token:width}` placeholders in `template` against
+// `fields`, e.g. `{year}/{month}/{camera_model}/{date}_{orig_name}` or
+// `{year}-{month}-{day}/{counter:04}`. Literal path separators in the
+// template carry straight through and become nested directories. An unknown
+// token is an error rather than passed through literally, so a typo in
+// config surfaces immediately instead of producing a confusing path.
+pub fn expand(template: &str, fields: &TemplateFields) -> Result<String> {
+    let re = Regex::new(r"\{(?P<key>[a-zA-Z_]+)(:(?P<width>[0-9]+))?\}").unwrap();
+
+    let mut result = String::new();
+    let mut last_end = 0;
+
+    for captures in re.captures_iter(template) {
+        let whole = captures.get(0).unwrap();
+        result.push_str(&template[last_end..whole.start()]);
+
+        let key = captures.name("key").unwrap().as_str();
+
+        let value = if key == "counter" {
+            fields.counter.to_string()
+        } else {
+            fields.lookup(key)
+                .ok_or_else(|| anyhow!("Unknown token '{{{}}}' in output template '{}'", key, template))?
+                .to_string()
+        };
+
+        let value = sanitize(&value);
+
+        match captures.name("width") {
+            Some(width) => {
+                let width: usize = width.as_str().parse().unwrap();
+                result.push_str(&format!("{:0>width$}", value, width = width));
+            }
+            None => result.push_str(&value),
+        }
+
+        last_end = whole.end();
+    }
+
+    result.push_str(&template[last_end..]);
+
+    if result.trim().is_empty() {
+        return Err(anyhow!("Output template '{}' expanded to an empty path", template));
+    }
+
+    Ok(result)
+}
+
+// per-run state an output template needs beyond what a single `Inspection`
+// carries: a monotonic counter for `{counter}`, and the set of destination
+// paths already handed out this run, so two sources whose template expands
+// to the same target don't overwrite one another
+pub struct TemplateRun {
+    counter: AtomicU32,
+    reserved: Mutex<HashSet<PathBuf>>,
+}
+
+impl TemplateRun {
+    pub fn new() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+            reserved: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn next_counter(&self) -> u32 {
+        self.counter.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // claim `path` for this run, appending `_1`, `_2`, ... right before the
+    // extension until it no longer collides with anything already claimed
+    pub fn reserve(&self, path: PathBuf) -> PathBuf {
+        let mut reserved = self.reserved.lock().unwrap();
+
+        if reserved.insert(path.clone()) {
+            return path;
+        }
+
+        let mut n = 1_u32;
+        loop {
+            let candidate = suffixed(&path, n);
+
+            if reserved.insert(candidate.clone()) {
+                return candidate;
+            }
+
+            n += 1;
+        }
+    }
+}
+
+fn suffixed(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let ext = path.extension().and_then(|s| s.to_str());
+
+    let filename = match ext {
+        Some(ext) => format!("{}_{}.{}", stem, n, ext),
+        None => format!("{}_{}", stem, n),
+    };
+
+    match path.parent() {
+        Some(parent) => parent.join(filename),
+        None => PathBuf::from(filename),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> TemplateFields {
+        TemplateFields {
+            year: "2024".to_string(),
+            month: "03".to_string(),
+            day: "02".to_string(),
+            date: "2024-03-02".to_string(),
+            camera_make: "Canon".to_string(),
+            camera_model: "EOS R5".to_string(),
+            orig_name: "IMG_1234.CR2".to_string(),
+            orig_stem: "IMG_1234".to_string(),
+            orig_ext: "CR2".to_string(),
+            counter: 7,
+        }
+    }
+
+    #[test]
+    fn expands_known_tokens() {
+        let out = expand("{year}/{month}/{camera_model}/{date}_{orig_name}", &fields()).unwrap();
+        assert_eq!(out, "2024/03/EOS R5/2024-03-02_IMG_1234.CR2");
+    }
+
+    #[test]
+    fn pads_width_specifier() {
+        let out = expand("{year}-{month}-{day}/{counter:04}", &fields()).unwrap();
+        assert_eq!(out, "2024-03-02/0007");
+    }
+
+    #[test]
+    fn rejects_unknown_token() {
+        assert!(expand("{bogus}", &fields()).is_err());
+    }
+
+    #[test]
+    fn sanitizes_slashes_in_token_values() {
+        let mut f = fields();
+        f.camera_model = "weird/model".to_string();
+
+        let out = expand("{camera_model}", &f).unwrap();
+        assert_eq!(out, "weird_model");
+    }
+
+    #[test]
+    fn reserves_unique_paths_on_collision() {
+        let run = TemplateRun::new();
+
+        let a = run.reserve(PathBuf::from("/out/2024/model.jpg"));
+        let b = run.reserve(PathBuf::from("/out/2024/model.jpg"));
+        let c = run.reserve(PathBuf::from("/out/2024/model.jpg"));
+
+        assert_eq!(a, PathBuf::from("/out/2024/model.jpg"));
+        assert_eq!(b, PathBuf::from("/out/2024/model_1.jpg"));
+        assert_eq!(c, PathBuf::from("/out/2024/model_2.jpg"));
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::{anyhow, Result};
+use webp::Encoder;
+
+use crate::processor::avif::load_rgba;
+
+pub struct EncodedImage {
+    pub webp_file: Vec<u8>,
+}
+
+// like AVIF, WebP bypasses ImageMagick's own (limited) WebP support and goes
+// through a dedicated encoder crate instead; quality 100 switches to
+// lossless, since that's the point at which a lossy encode stops making sense.
+pub fn encode(blob: Vec<u8>, quality: f32) -> Result<EncodedImage> {
+    let img = load_rgba(&blob, false)
+        .map_err(|e| anyhow!("Failed to load rgb: {}", e))?;
+
+    let width = img.width() as u32;
+    let height = img.height() as u32;
+    let rgba: Vec<u8> = img.pixels().flat_map(|px| [px.r, px.g, px.b, px.a]).collect();
+
+    let encoder = Encoder::from_rgba(&rgba, width, height);
+
+    let memory = if quality >= 100. {
+        encoder.encode_lossless()
+    } else {
+        encoder.encode(quality)
+    };
+
+    Ok(EncodedImage {
+        webp_file: memory.to_vec(),
+    })
+}
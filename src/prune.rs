@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{fs, process};
+
+use chrono::{Datelike, NaiveDate};
+use console::style;
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::progress::{PanelType, Progress, Update};
+
+// how many most-recent buckets to keep per retention interval, proxmox-backup
+// style; a `None` (or `Some(0)`) disables that interval entirely and leaves
+// the decision to whichever other intervals are configured
+pub struct RetentionPolicy {
+    pub keep_daily: Option<u32>,
+    pub keep_weekly: Option<u32>,
+    pub keep_monthly: Option<u32>,
+    pub keep_yearly: Option<u32>,
+}
+
+impl RetentionPolicy {
+    fn is_empty(&self) -> bool {
+        self.keep_daily.is_none() && self.keep_weekly.is_none()
+            && self.keep_monthly.is_none() && self.keep_yearly.is_none()
+    }
+}
+
+// one file found under a `<import_to>/YYYY/YYYY-MM-DD` directory, carrying
+// the capture date its parent directory name encodes
+struct DatedFile {
+    path: PathBuf,
+    date: NaiveDate,
+}
+
+pub fn do_prune(conf: Config, policy: RetentionPolicy, dry_run: bool) {
+    if policy.is_empty() {
+        eprintln!("At least one of --keep-daily/--keep-weekly/--keep-monthly/--keep-yearly is required");
+        process::exit(1);
+    }
+
+    let import_to = conf.import_to();
+
+    // pruning deletes files directly via `std::fs`, so (unlike clone/drive
+    // sync) it only understands a local destination for now
+    if import_to.to_str().map_or(false, |s| s.starts_with("sftp://")) {
+        eprintln!("Prune only supports a local destination, not an sftp:// one");
+        process::exit(1);
+    }
+
+    println!("{} {}", style("Scanning").green().bold(), import_to.to_str().unwrap());
+
+    let files = collect_dated_files(import_to);
+    let to_remove = plan_removals(files, &policy);
+
+    if to_remove.is_empty() {
+        println!("Nothing to prune");
+        return;
+    }
+
+    let progress = Progress::new(vec![
+        PanelType::Bar("files_bar", to_remove.len() as u64),
+        PanelType::Message("state"),
+    ]);
+
+    let mut removed = 0;
+    let mut errors = Vec::new();
+
+    for file in &to_remove {
+        progress.update("files_bar", Update::Incr(None));
+        let path_str = file.path.to_str().unwrap();
+
+        if dry_run {
+            progress.update("state", Update::Incr(Some(format!("{}: would remove...", style(path_str).bold()))));
+            continue;
+        }
+
+        progress.update("state", Update::Incr(Some(format!("{}: removing...", style(path_str).bold()))));
+
+        match fs::remove_file(&file.path) {
+            Ok(()) => removed += 1,
+            Err(e) => errors.push((path_str.to_string(), e)),
+        }
+    }
+
+    progress.finish_all();
+    progress.clear();
+
+    if dry_run {
+        println!("{:>5} files in '{}' would be pruned:", style(to_remove.len()).cyan().bold(), import_to.to_str().unwrap());
+        for file in &to_remove {
+            println!("  {} {}", style("-").yellow(), file.path.to_str().unwrap());
+        }
+    } else {
+        println!("{:>5} files pruned", style(removed).cyan().bold());
+    }
+
+    if !errors.is_empty() {
+        println!("{}", style("---").dim());
+        println!("Errors:");
+        for (path, e) in &errors {
+            println!("{} {}: {}", style("-").red(), style(path.as_str()).red().bold(), e);
+        }
+    }
+}
+
+// walk `<import_to>/<year>/<YYYY-MM-DD>/*`, the default layout
+// `image::process` (processor/image.rs) builds; anything that isn't a dated day directory two levels
+// down (the manifest, the geotag export, `.kapy-state`, ...) is left alone
+fn collect_dated_files(import_to: &Path) -> Vec<DatedFile> {
+    let mut files = Vec::new();
+
+    for day_dir in WalkDir::new(import_to)
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        let date = match day_dir.file_name().to_str().and_then(|name| NaiveDate::parse_from_str(name, "%Y-%m-%d").ok()) {
+            Some(date) => date,
+            None => continue,
+        };
+
+        for file in WalkDir::new(day_dir.path())
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            files.push(DatedFile { path: file.path().to_path_buf(), date });
+        }
+    }
+
+    files
+}
+
+// the algorithm proxmox-backup uses for its retention rules: walking newest
+// to oldest per interval, an item is kept as long as its bucket (day/ISO
+// week/month/year) is one of the first `keep_n` distinct buckets seen for
+// that interval; an item surviving under any configured interval is kept,
+// everything else is returned for removal
+fn plan_removals(mut files: Vec<DatedFile>, policy: &RetentionPolicy) -> Vec<DatedFile> {
+    files.sort_by(|a, b| b.date.cmp(&a.date));
+
+    let mut keep = vec![false; files.len()];
+
+    keep_most_recent_buckets(&files, policy.keep_daily, &mut keep, |d| d.to_string());
+    keep_most_recent_buckets(&files, policy.keep_weekly, &mut keep, |d| {
+        let w = d.iso_week();
+        format!("{}-W{:02}", w.year(), w.week())
+    });
+    keep_most_recent_buckets(&files, policy.keep_monthly, &mut keep, |d| format!("{}-{:02}", d.year(), d.month()));
+    keep_most_recent_buckets(&files, policy.keep_yearly, &mut keep, |d| d.year().to_string());
+
+    files.into_iter()
+        .zip(keep)
+        .filter(|(_, kept)| !kept)
+        .map(|(file, _)| file)
+        .collect()
+}
+
+fn keep_most_recent_buckets<F>(files: &[DatedFile], keep_n: Option<u32>, keep: &mut [bool], bucket_key: F)
+    where F: Fn(&NaiveDate) -> String
+{
+    let keep_n = match keep_n {
+        Some(n) if n > 0 => n,
+        _ => return,
+    };
+
+    let mut seen = HashSet::new();
+
+    for (i, file) in files.iter().enumerate() {
+        let key = bucket_key(&file.date);
+
+        if !seen.contains(&key) {
+            if seen.len() as u32 >= keep_n {
+                continue;
+            }
+            seen.insert(key);
+        }
+
+        keep[i] = true;
+    }
+}
@@ -0,0 +1,31 @@
+use std::path::Path;
+use std::process;
+
+use console::style;
+
+use crate::config;
+use crate::drive::GoogleDrive;
+use crate::drive::auth::{AuthFlow, GoogleAuthenticator, ListenPort, Store};
+use crate::drive::sync;
+
+// thin CLI wrapper around `drive::sync::sync`; kept as its own module (rather
+// than folding into `drive::sync`) since it's a command handler like
+// `clean`/`clone`/`login`, not part of the sync algorithm itself
+pub fn do_sync(cred_path: &Path, query: &str, to: &Path, page_size: usize, full: bool) {
+    let auth = GoogleAuthenticator::new(ListenPort::DefaultPort, Store::File(cred_path), AuthFlow::Browser);
+    let drive = GoogleDrive::new(auth);
+
+    let app_home = config::default_path().app_home();
+
+    println!("{} '{}' -> '{}'", style("Syncing").green().bold(), query, to.to_str().unwrap());
+
+    match sync::sync(&drive, app_home.as_ref(), to, query, page_size, full, |filename| {
+        println!("  {} {}", style("downloading").bold(), filename);
+    }) {
+        Ok(downloaded) => println!("{:>5} files downloaded", style(downloaded).cyan().bold()),
+        Err(e) => {
+            eprintln!("Failed to sync: {:?}", e);
+            process::exit(1);
+        }
+    }
+}